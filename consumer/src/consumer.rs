@@ -1,4 +1,4 @@
-use crate::{Config, Database, Processor};
+use crate::{Config, Database, Processor, RecordKind};
 use log::{error, info, warn};
 use rdkafka::{
     config::ClientConfig,
@@ -54,7 +54,8 @@ impl Consumer {
                         Ok(message) => {
                             if let Some(payload) = message.payload() {
                                 let topic = message.topic();
-                                if let Err(e) = self.processor.process_message(topic, payload).await {
+                                let kind = message.headers().and_then(RecordKind::from_headers);
+                                if let Err(e) = self.processor.process_message(topic, kind, payload).await {
                                     error!("Failed to process message from topic {}: {:?}", topic, e);
                                 }
                             }
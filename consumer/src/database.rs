@@ -82,12 +82,22 @@ PARTITION BY toYYYYMM(created_at)
             .await?;
 
         self.client
-            .query(
-                r#"
-CREATE TABLE IF NOT EXISTS transactions (
+            .query(&Self::transactions_table("transactions"))
+            .execute()
+            .await?;
+        self.migrate_transaction_index().await?;
+
+        info!("Database tables created/verified");
+        Ok(())
+    }
+
+    fn transactions_table(name: &str) -> String {
+        format!(
+            r#"
+CREATE TABLE IF NOT EXISTS {name} (
     signature String,
     slot UInt64,
-    `index` UInt64,
+    `index` Nullable(UInt64),
     is_vote Bool,
     is_successful Bool,
     fee UInt64,
@@ -98,12 +108,47 @@ CREATE TABLE IF NOT EXISTS transactions (
 ) ENGINE = MergeTree()
 ORDER BY (slot, `index`)
 PARTITION BY toYYYYMM(created_at)
-        "#,
-            )
+SETTINGS allow_nullable_key = 1
+        "#
+        )
+    }
+
+    /// Moves a transactions table created before `index` was nullable to the
+    /// current schema. ClickHouse can't change the type of a sorting key column in
+    /// place, so the rows are copied into a new table that then takes its name.
+    /// The old table is kept as `transactions_non_nullable_index`.
+    async fn migrate_transaction_index(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let index_type = self
+            .client
+            .query("SELECT type FROM system.columns WHERE database = currentDatabase() AND table = 'transactions' AND name = 'index'")
+            .fetch_optional::<String>()
+            .await?;
+        if index_type.as_deref() != Some("UInt64") {
+            return Ok(());
+        }
+
+        info!("Migrating transactions table to a nullable `index` column");
+        // Rows of an interrupted migration are copied again from the start.
+        self.client
+            .query("DROP TABLE IF EXISTS transactions_migrated")
+            .execute()
+            .await?;
+        self.client
+            .query(&Self::transactions_table("transactions_migrated"))
+            .execute()
+            .await?;
+        self.client
+            .query("INSERT INTO transactions_migrated SELECT signature, slot, `index`, is_vote, is_successful, fee, compute_units_consumed, num_instructions, num_accounts, created_at FROM transactions")
+            .execute()
+            .await?;
+        self.client
+            .query("RENAME TABLE transactions TO transactions_non_nullable_index, transactions_migrated TO transactions")
             .execute()
             .await?;
 
-        info!("Database tables created/verified");
+        info!(
+            "Migrated transactions table, the previous one is kept as transactions_non_nullable_index and can be dropped"
+        );
         Ok(())
     }
 
@@ -169,16 +214,20 @@ PARTITION BY toYYYYMM(created_at)
             return Ok(());
         }
 
+        // The query binder has no NULL, so nullable columns are bound as a flag and a
+        // value and picked with `if`.
         for tx in transactions {
             self.client
-                .query("INSERT INTO transactions (signature, slot, `index`, is_vote, is_successful, fee, compute_units_consumed, num_instructions, num_accounts, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
+                .query("INSERT INTO transactions (signature, slot, `index`, is_vote, is_successful, fee, compute_units_consumed, num_instructions, num_accounts, created_at) VALUES (?, ?, if(?, ?, NULL), ?, ?, ?, if(?, ?, NULL), ?, ?, ?)")
                 .bind(&tx.signature)
                 .bind(tx.slot)
-                .bind(tx.index)
+                .bind(tx.index.is_some())
+                .bind(tx.index.unwrap_or_default())
                 .bind(tx.is_vote)
                 .bind(tx.is_successful)
                 .bind(tx.fee)
-                .bind(tx.compute_units_consumed.unwrap_or(0))
+                .bind(tx.compute_units_consumed.is_some())
+                .bind(tx.compute_units_consumed.unwrap_or_default())
                 .bind(tx.num_instructions)
                 .bind(tx.num_accounts)
                 .bind(tx.created_at.timestamp() as i32)
@@ -1,9 +1,5 @@
-include!(concat!(
-    env!("OUT_DIR"),
-    "/heimdall.solana.geyser_plugin_kafka.types.rs"
-));
-
 use chrono::{DateTime, Utc};
+use heimdall_proto::{SlotStatus, SlotStatusEvent, TransactionEvent, UpdateAccountEvent};
 
 #[derive(Debug, Clone)]
 pub struct AccountRow {
@@ -24,6 +20,8 @@ pub struct SlotRow {
     pub slot: u64,
     pub parent: u64,
     pub status: String,
+    /// Block time of the slot if the consumer saw its block metadata, else the
+    /// time the row was read.
     pub created_at: DateTime<Utc>,
 }
 
@@ -31,13 +29,16 @@ pub struct SlotRow {
 pub struct TransactionRow {
     pub signature: String,
     pub slot: u64,
-    pub index: u64,
+    /// Position in the block, not reported by validators that predate it.
+    pub index: Option<u64>,
     pub is_vote: bool,
     pub is_successful: bool,
     pub fee: u64,
     pub compute_units_consumed: Option<u64>,
     pub num_instructions: u32,
     pub num_accounts: u32,
+    /// Block time of the slot if the consumer saw its block metadata, else the
+    /// time the row was read.
     pub created_at: DateTime<Utc>,
 }
 
@@ -50,7 +51,7 @@ impl From<UpdateAccountEvent> for AccountRow {
             owner: bs58::encode(&event.owner).into_string(),
             executable: event.executable,
             rent_epoch: event.rent_epoch,
-            data_len: event.data.len() as u64,
+            data_len: event.data_len.unwrap_or(event.data.len() as u64),
             write_version: event.write_version,
             txn_signature: event
                 .txn_signature
@@ -62,15 +63,15 @@ impl From<UpdateAccountEvent> for AccountRow {
 
 impl From<SlotStatusEvent> for SlotRow {
     fn from(event: SlotStatusEvent) -> Self {
-        let status_str = match SlotStatus::from_i32(event.status) {
-            Some(SlotStatus::Processed) => "Processed".to_string(),
-            Some(SlotStatus::Rooted) => "Rooted".to_string(),
-            Some(SlotStatus::Confirmed) => "Confirmed".to_string(),
-            Some(SlotStatus::FirstShredReceived) => "FirstShredReceived".to_string(),
-            Some(SlotStatus::Completed) => "Completed".to_string(),
-            Some(SlotStatus::CreatedBank) => "CreatedBank".to_string(),
-            Some(SlotStatus::Dead) => "Dead".to_string(),
-            None => "Unknown".to_string(),
+        let status_str = match SlotStatus::try_from(event.status) {
+            Ok(SlotStatus::Processed) => "Processed".to_string(),
+            Ok(SlotStatus::Rooted) => "Rooted".to_string(),
+            Ok(SlotStatus::Confirmed) => "Confirmed".to_string(),
+            Ok(SlotStatus::FirstShredReceived) => "FirstShredReceived".to_string(),
+            Ok(SlotStatus::Completed) => "Completed".to_string(),
+            Ok(SlotStatus::CreatedBank) => "CreatedBank".to_string(),
+            Ok(SlotStatus::Dead) => "Dead".to_string(),
+            Err(_) => "Unknown".to_string(),
         };
 
         Self {
@@ -84,17 +85,24 @@ impl From<SlotStatusEvent> for SlotRow {
 
 impl From<TransactionEvent> for TransactionRow {
     fn from(event: TransactionEvent) -> Self {
-        let (is_successful, fee, num_instructions, num_accounts) =
+        let (is_successful, fee, num_instructions) =
             if let Some(meta) = &event.transaction_status_meta {
                 (
                     !meta.is_status_err,
                     meta.fee,
                     meta.inner_instructions.len() as u32,
-                    0u32,
                 )
             } else {
-                (true, 0, 0, 0)
+                (true, 0, 0)
             };
+        let num_accounts = event
+            .transaction
+            .as_ref()
+            .map_or(0, |tx| tx.accounts.len() as u32);
+        let compute_units_consumed = event
+            .transaction_status_meta
+            .as_ref()
+            .and_then(|meta| meta.compute_units_consumed);
 
         Self {
             signature: bs58::encode(&event.signature).into_string(),
@@ -103,7 +111,7 @@ impl From<TransactionEvent> for TransactionRow {
             is_vote: event.is_vote,
             is_successful,
             fee,
-            compute_units_consumed: None,
+            compute_units_consumed,
             num_instructions,
             num_accounts,
             created_at: Utc::now(),
@@ -4,4 +4,9 @@ mod database;
 mod event;
 mod processor;
 
-pub use {config::Config, consumer::Consumer, database::Database, processor::Processor};
+pub use {
+    config::Config,
+    consumer::Consumer,
+    database::Database,
+    processor::{Processor, RecordKind},
+};
@@ -1,15 +1,70 @@
 use crate::{
     Database,
-    event::{AccountRow, MessageWrapper, SlotRow, TransactionRow},
+    event::{AccountRow, SlotRow, TransactionRow},
 };
-use log::warn;
+use chrono::{DateTime, Utc};
+use heimdall_proto::{
+    BlockMetadataEvent, MessageWrapper, SlotStatusEvent, TransactionEvent, UpdateAccountEvent,
+    message_wrapper::EventMessage, strip_confluent_framing,
+};
+use log::{debug, warn};
 use prost::Message;
+use rdkafka::message::{BorrowedHeaders, Headers};
+use std::collections::BTreeMap;
+
+/// Number of recent slots whose block time is kept to stamp their rows.
+const BLOCK_TIME_SLOTS: usize = 10_000;
+
+/// What a record holds according to the `heimdall-event` and `heimdall-format`
+/// headers the plugin attaches.
+#[derive(Debug, Clone, Copy)]
+pub struct RecordKind<'a> {
+    pub event: &'a str,
+    /// `protobuf`, `protobuf-wrapped`, `json` or `confluent-protobuf`.
+    pub format: &'a str,
+}
+
+impl<'a> RecordKind<'a> {
+    pub fn from_headers(headers: &'a BorrowedHeaders) -> Option<Self> {
+        let mut event = None;
+        let mut format = None;
+        let mut wrapped = None;
+        for header in headers.iter() {
+            let value = header
+                .value
+                .and_then(|value| std::str::from_utf8(value).ok());
+            match header.key {
+                "heimdall-event" => event = value,
+                "heimdall-format" => format = value,
+                "heimdall-wrapped" => wrapped = value,
+                _ => {}
+            }
+        }
+        // Records from before the format header only say whether they are wrapped.
+        let format = format.or_else(|| {
+            wrapped.map(|wrapped| {
+                if wrapped == "true" {
+                    "protobuf-wrapped"
+                } else {
+                    "protobuf"
+                }
+            })
+        });
+        Some(Self {
+            event: event?,
+            format: format?,
+        })
+    }
+}
 
 pub struct Processor {
     database: Database,
     account_batch: Vec<AccountRow>,
     slot_batch: Vec<SlotRow>,
     transaction_batch: Vec<TransactionRow>,
+    /// Block times from block metadata events, used as `created_at` of slot and
+    /// transaction rows of the same slot.
+    block_times: BTreeMap<u64, DateTime<Utc>>,
     batch_size: usize,
 }
 
@@ -20,63 +75,159 @@ impl Processor {
             account_batch: Vec::with_capacity(batch_size),
             slot_batch: Vec::with_capacity(batch_size),
             transaction_batch: Vec::with_capacity(batch_size),
+            block_times: BTreeMap::new(),
             batch_size,
         }
     }
 
+    /// Decodes a record and adds it to its batch. Records without Heimdall headers
+    /// are identified by trying the wrapper first and then by topic name.
     pub async fn process_message(
         &mut self,
         topic: &str,
+        kind: Option<RecordKind<'_>>,
         payload: &[u8],
     ) -> Result<(), Box<dyn std::error::Error>> {
-        if let Ok(wrapper) = MessageWrapper::decode(payload) {
-            if let Some(event_message) = wrapper.event_message {
-                match event_message {
-                    crate::event::message_wrapper::EventMessage::Account(account_event) => {
-                        self.account_batch.push(AccountRow::from(account_event));
-                    }
-                    crate::event::message_wrapper::EventMessage::Slot(slot_event) => {
-                        self.slot_batch.push(SlotRow::from(slot_event));
-                    }
-                    crate::event::message_wrapper::EventMessage::Transaction(tx_event) => {
-                        self.transaction_batch.push(TransactionRow::from(tx_event));
+        match kind {
+            Some(RecordKind {
+                event,
+                format: "protobuf-wrapped",
+            }) => match MessageWrapper::decode(payload) {
+                Ok(wrapper) => self.process_wrapper(wrapper),
+                Err(_) => warn!("Failed to decode wrapped {} message", event),
+            },
+            Some(RecordKind {
+                event,
+                format: "protobuf",
+            }) => self.process_event(event, payload),
+            Some(RecordKind {
+                event,
+                format: "confluent-protobuf",
+            }) => match strip_confluent_framing(payload) {
+                Some(payload) => self.process_event(event, payload),
+                None => warn!("Failed to read Confluent framing of {} message", event),
+            },
+            Some(RecordKind { event, format }) => {
+                warn!(
+                    "Skipping {} message in unsupported format {}",
+                    event, format
+                );
+            }
+            None => {
+                if let Ok(wrapper) = MessageWrapper::decode(payload) {
+                    self.process_wrapper(wrapper);
+                } else {
+                    match topic {
+                        t if t.contains("account") => self.process_event("account", payload),
+                        t if t.contains("slot") => self.process_event("slot", payload),
+                        t if t.contains("transaction") => {
+                            self.process_event("transaction", payload)
+                        }
+                        t if t.contains("block") => self.process_event("block_metadata", payload),
+                        _ => {
+                            warn!("Unknown topic: {}", topic);
+                        }
                     }
                 }
             }
-        } else {
-            match topic {
-                t if t.contains("account") => {
-                    if let Ok(account_event) = crate::event::UpdateAccountEvent::decode(payload) {
-                        self.account_batch.push(AccountRow::from(account_event));
-                    } else {
-                        warn!("Failed to decode account message");
-                    }
+        }
+
+        self.flush_if_needed().await?;
+        Ok(())
+    }
+
+    fn process_wrapper(&mut self, wrapper: MessageWrapper) {
+        if let Some(event_message) = wrapper.event_message {
+            match event_message {
+                EventMessage::Account(account_event) => {
+                    self.account_batch.push(AccountRow::from(account_event));
                 }
-                t if t.contains("slot") => {
-                    if let Ok(slot_event) = crate::event::SlotStatusEvent::decode(payload) {
-                        self.slot_batch.push(SlotRow::from(slot_event));
-                    } else {
-                        warn!("Failed to decode slot message");
-                    }
+                EventMessage::Slot(slot_event) => {
+                    self.slot_batch.push(SlotRow::from(slot_event));
                 }
-                t if t.contains("transaction") => {
-                    if let Ok(tx_event) = crate::event::TransactionEvent::decode(payload) {
-                        self.transaction_batch.push(TransactionRow::from(tx_event));
-                    } else {
-                        warn!("Failed to decode transaction message");
-                    }
+                EventMessage::Transaction(tx_event) => {
+                    self.transaction_batch.push(TransactionRow::from(tx_event));
+                }
+                EventMessage::BlockMetadata(block_event) => {
+                    self.record_block_time(&block_event);
                 }
-                _ => {
-                    warn!("Unknown topic: {}", topic);
+                EventMessage::Entry(entry_event) => {
+                    debug!(
+                        "Skipping entry {} for slot {}",
+                        entry_event.index, entry_event.slot
+                    );
                 }
             }
         }
+    }
 
-        self.flush_if_needed().await?;
-        Ok(())
+    /// Decodes an unwrapped event of the kind named by `event`.
+    fn process_event(&mut self, event: &str, payload: &[u8]) {
+        match event {
+            "account" => {
+                if let Ok(account_event) = UpdateAccountEvent::decode(payload) {
+                    self.account_batch.push(AccountRow::from(account_event));
+                } else {
+                    warn!("Failed to decode account message");
+                }
+            }
+            "slot" => {
+                if let Ok(slot_event) = SlotStatusEvent::decode(payload) {
+                    self.slot_batch.push(SlotRow::from(slot_event));
+                } else {
+                    warn!("Failed to decode slot message");
+                }
+            }
+            "transaction" => {
+                if let Ok(tx_event) = TransactionEvent::decode(payload) {
+                    self.transaction_batch.push(TransactionRow::from(tx_event));
+                } else {
+                    warn!("Failed to decode transaction message");
+                }
+            }
+            "block_metadata" => {
+                if let Ok(block_event) = BlockMetadataEvent::decode(payload) {
+                    self.record_block_time(&block_event);
+                } else {
+                    warn!("Failed to decode block metadata message");
+                }
+            }
+            _ => {
+                debug!("Skipping {} message", event);
+            }
+        }
+    }
+
+    fn record_block_time(&mut self, event: &BlockMetadataEvent) {
+        let Some(block_time) = event
+            .block_time
+            .and_then(|block_time| DateTime::from_timestamp(block_time, 0))
+        else {
+            debug!("Block metadata of slot {} has no block time", event.slot);
+            return;
+        };
+        self.block_times.insert(event.slot, block_time);
+        if self.block_times.len() > BLOCK_TIME_SLOTS {
+            self.block_times.pop_first();
+        }
+    }
+
+    /// Replaces the `created_at` of rows whose slot has a known block time.
+    fn stamp_block_times(&mut self) {
+        for row in &mut self.slot_batch {
+            if let Some(block_time) = self.block_times.get(&row.slot) {
+                row.created_at = *block_time;
+            }
+        }
+        for row in &mut self.transaction_batch {
+            if let Some(block_time) = self.block_times.get(&row.slot) {
+                row.created_at = *block_time;
+            }
+        }
     }
 
     pub async fn flush_all(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.stamp_block_times();
         if !self.account_batch.is_empty() {
             self.database.insert_accounts(&self.account_batch).await?;
             self.account_batch.clear();
@@ -103,6 +254,12 @@ impl Processor {
             self.account_batch.clear();
         }
 
+        if self.slot_batch.len() >= self.batch_size
+            || self.transaction_batch.len() >= self.batch_size
+        {
+            self.stamp_block_times();
+        }
+
         if self.slot_batch.len() >= self.batch_size {
             self.database.insert_slots(&self.slot_batch).await?;
             self.slot_batch.clear();
@@ -1,15 +1,22 @@
 use {
+    crate::{HeimdallProducerContext, Producer},
     agave_geyser_plugin_interface::geyser_plugin_interface::{
         GeyserPluginError, Result as PluginResult,
     },
+    base64::{Engine, prelude::BASE64_STANDARD},
     rdkafka::{
-        ClientConfig,
-        config::FromClientConfig,
-        error::KafkaResult,
-        producer::{DefaultProducerContext, ThreadedProducer},
+        ClientConfig, config::FromClientConfigAndContext, error::KafkaResult,
+        producer::ThreadedProducer,
+    },
+    regex::Regex,
+    serde::{Deserialize, Deserializer, de::Error as _},
+    solana_pubkey::Pubkey,
+    std::{
+        collections::{HashMap, HashSet},
+        fs::File,
+        path::{Path, PathBuf},
+        str::FromStr,
     },
-    serde::Deserialize,
-    std::{collections::HashMap, fs::File, path::Path},
 };
 
 #[derive(Debug, Deserialize)]
@@ -18,11 +25,39 @@ pub struct Config {
     #[allow(dead_code)]
     libpath: String,
 
+    /// Producer settings of the single-sink layout, loaded as a sink named `kafka`.
+    #[serde(default)]
     pub kafka: HashMap<String, String>,
 
     #[serde(default)]
     pub shutdown_timeout_ms: u64,
 
+    #[serde(default)]
+    pub pipeline: PipelineConfig,
+
+    #[serde(default)]
+    pub reload: ReloadConfig,
+
+    /// Spool of the single-sink layout.
+    #[serde(default)]
+    pub spool: Option<SpoolConfig>,
+
+    /// Dead-letter store of the single-sink layout.
+    #[serde(default)]
+    pub dead_letter: Option<SpoolConfig>,
+
+    /// Record headers of the single-sink layout.
+    #[serde(default)]
+    pub headers: Option<HeadersConfig>,
+
+    /// Named destinations that filters publish to.
+    #[serde(default)]
+    pub sinks: Vec<SinkConfig>,
+
+    /// Registry the schemas of `confluent-protobuf` topics are registered with.
+    #[serde(default)]
+    pub schema_registry: Option<SchemaRegistryConfig>,
+
     pub filters: Vec<ConfigFilter>,
 }
 
@@ -32,6 +67,13 @@ impl Default for Config {
             libpath: "".to_owned(),
             kafka: HashMap::new(),
             shutdown_timeout_ms: 30_000,
+            pipeline: PipelineConfig::default(),
+            reload: ReloadConfig::default(),
+            spool: None,
+            dead_letter: None,
+            headers: None,
+            sinks: vec![],
+            schema_registry: None,
             filters: vec![],
         }
     }
@@ -42,17 +84,281 @@ impl Config {
         let file = File::open(config_path)?;
         let mut this: Self = serde_json::from_reader(file)
             .map_err(|e| GeyserPluginError::ConfigFileReadError { msg: e.to_string() })?;
+        this.migrate_single_sink();
         this.fill_defaults();
+        this.validate()?;
         Ok(this)
     }
 
-    pub fn producer(&self) -> KafkaResult<ThreadedProducer<DefaultProducerContext>> {
+    /// Turns the top-level `kafka`, `spool` and `dead_letter` settings into a sink.
+    fn migrate_single_sink(&mut self) {
+        if self.kafka.is_empty()
+            && self.spool.is_none()
+            && self.dead_letter.is_none()
+            && self.headers.is_none()
+        {
+            return;
+        }
+
+        self.sinks.push(SinkConfig::Kafka(KafkaSinkConfig {
+            name: DEFAULT_SINK.to_owned(),
+            kafka: std::mem::take(&mut self.kafka),
+            spool: self.spool.take(),
+            dead_letter: self.dead_letter.take(),
+            headers: self.headers.take().unwrap_or_default(),
+            idempotent: false,
+            transactions: None,
+        }));
+    }
+
+    fn validate(&self) -> PluginResult<()> {
+        let mut names = HashSet::new();
+        for sink in &self.sinks {
+            if !names.insert(sink.name()) {
+                return Err(GeyserPluginError::ConfigFileReadError {
+                    msg: format!("sink \"{}\" is defined more than once", sink.name()),
+                });
+            }
+        }
+
+        for sink in &self.sinks {
+            if let SinkConfig::File(file) = sink
+                && (file.max_file_bytes == 0 || file.slots_per_file == Some(0))
+            {
+                return Err(GeyserPluginError::ConfigFileReadError {
+                    msg: format!(
+                        "sink \"{}\" needs a non-zero max_file_bytes and slots_per_file",
+                        file.name
+                    ),
+                });
+            }
+        }
+
+        for sink in &self.sinks {
+            if let SinkConfig::Kafka(kafka) = sink
+                && let Some(identity) = &kafka.headers.validator_identity
+                && let Err(e) = Pubkey::from_str(identity)
+            {
+                return Err(GeyserPluginError::ConfigFileReadError {
+                    msg: format!(
+                        "sink \"{}\" has an invalid validator_identity {identity:?}: {e}",
+                        kafka.name
+                    ),
+                });
+            }
+        }
+
+        for sink in &self.sinks {
+            if let SinkConfig::Kafka(kafka) = sink
+                && kafka.transactions.is_some()
+                && kafka.spool.is_some()
+            {
+                return Err(GeyserPluginError::ConfigFileReadError {
+                    msg: format!(
+                        "sink \"{}\" cannot use transactions together with a spool",
+                        kafka.name
+                    ),
+                });
+            }
+        }
+
+        let mut problems = Vec::new();
+        for (index, filter) in self.filters.iter().enumerate() {
+            if let Err(filter_problems) = filter.validate() {
+                problems.extend(
+                    filter_problems
+                        .into_iter()
+                        .map(|problem| format!("filter {index}: {problem}")),
+                );
+            }
+
+            if filter.format() == MessageFormat::ConfluentProtobuf && self.schema_registry.is_none()
+            {
+                problems.push(format!(
+                    "filter {index}: the confluent-protobuf format requires a schema_registry"
+                ));
+            }
+
+            match self.sinks.iter().find(|sink| sink.name() == filter.sink) {
+                None => problems.push(format!(
+                    "filter {index}: routes to unknown sink \"{}\"",
+                    filter.sink
+                )),
+                Some(SinkConfig::File(_) | SinkConfig::Socket(_))
+                    if filter.format() != MessageFormat::ProtobufWrapped =>
+                {
+                    problems.push(format!(
+                        "filter {index}: routes to sink \"{}\" which only accepts the protobuf-wrapped format",
+                        filter.sink
+                    ))
+                }
+                Some(_) => {}
+            }
+        }
+        if !problems.is_empty() {
+            return Err(GeyserPluginError::ConfigFileReadError {
+                msg: format!("invalid filters: {}", problems.join("; ")),
+            });
+        }
+
+        if self.pipeline.overflow_policy == OverflowPolicy::SpillToDisk {
+            for sink in &self.sinks {
+                if let SinkConfig::Kafka(kafka) = sink
+                    && kafka.spool.is_none()
+                {
+                    return Err(GeyserPluginError::ConfigFileReadError {
+                        msg: format!(
+                            "overflow_policy \"spill-to-disk\" requires a spool on sink \"{}\"",
+                            kafka.name
+                        ),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn fill_defaults(&mut self) {
+        for sink in &mut self.sinks {
+            match sink {
+                SinkConfig::Kafka(kafka) => kafka.fill_defaults(),
+                SinkConfig::File(_) | SinkConfig::Socket(_) => {}
+            }
+        }
+    }
+}
+
+/// Name of the sink built from the top-level `kafka` settings.
+pub const DEFAULT_SINK: &str = "kafka";
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+#[allow(clippy::large_enum_variant)]
+pub enum SinkConfig {
+    Kafka(KafkaSinkConfig),
+    File(FileSinkConfig),
+    Socket(SocketSinkConfig),
+}
+
+impl SinkConfig {
+    pub fn name(&self) -> &str {
+        match self {
+            SinkConfig::Kafka(kafka) => &kafka.name,
+            SinkConfig::File(file) => &file.name,
+            SinkConfig::Socket(socket) => &socket.name,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KafkaSinkConfig {
+    pub name: String,
+
+    pub kafka: HashMap<String, String>,
+
+    /// Where records are kept while the brokers are unreachable.
+    #[serde(default)]
+    pub spool: Option<SpoolConfig>,
+
+    /// Where records that Kafka permanently failed to deliver are written.
+    #[serde(default)]
+    pub dead_letter: Option<SpoolConfig>,
+
+    /// Metadata attached to every record as Kafka headers.
+    #[serde(default)]
+    pub headers: HeadersConfig,
+
+    /// Turn on the idempotent producer, so retries cannot duplicate or reorder records.
+    #[serde(default)]
+    pub idempotent: bool,
+
+    /// Publish the records of each slot in one Kafka transaction. Implies `idempotent`.
+    #[serde(default)]
+    pub transactions: Option<TransactionsConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TransactionsConfig {
+    /// Must be unique per plugin instance and stable across restarts.
+    pub transactional_id: String,
+    /// Slot status at which a slot's records are committed.
+    #[serde(default)]
+    pub commit_on: CommitStatus,
+    /// How long initializing, committing or aborting a transaction may take.
+    #[serde(default = "default_transaction_timeout_ms")]
+    pub timeout_ms: u64,
+    /// Records that arrived after their slot was committed, kept for the next slot.
+    /// Records beyond this go to the dead-letter store.
+    #[serde(default = "default_max_late_records")]
+    pub max_late_records: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CommitStatus {
+    Completed,
+    #[default]
+    Confirmed,
+}
+
+fn default_transaction_timeout_ms() -> u64 {
+    10_000
+}
+
+fn default_max_late_records() -> usize {
+    100_000
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SchemaRegistryConfig {
+    /// Base URL of a Confluent compatible schema registry.
+    pub url: String,
+    /// Basic auth credentials, if the registry requires them.
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    /// How long a registry request may take.
+    #[serde(default = "default_registry_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+fn default_registry_timeout_ms() -> u64 {
+    5_000
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct HeadersConfig {
+    /// Attach headers to records.
+    pub enabled: bool,
+    /// Identifies the host running the plugin, sent as `heimdall-host`.
+    pub host_id: Option<String>,
+    /// Identity pubkey of the validator, sent as `heimdall-validator`.
+    pub validator_identity: Option<String>,
+}
+
+impl Default for HeadersConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            host_id: None,
+            validator_identity: None,
+        }
+    }
+}
+
+impl KafkaSinkConfig {
+    pub fn producer(&self, context: HeimdallProducerContext) -> KafkaResult<Producer> {
         let mut config = ClientConfig::new();
         for (k, v) in self.kafka.iter() {
             config.set(k, v);
         }
 
-        ThreadedProducer::from_config(&config)
+        ThreadedProducer::from_config_and_context(&config, context)
     }
 
     fn set_default(&mut self, k: &'static str, v: &'static str) {
@@ -62,6 +368,17 @@ impl Config {
     }
 
     fn fill_defaults(&mut self) {
+        if let Some(transactions) = &self.transactions {
+            self.kafka.insert(
+                "transactional.id".to_owned(),
+                transactions.transactional_id.clone(),
+            );
+        }
+        if self.idempotent || self.transactions.is_some() {
+            self.kafka
+                .insert("enable.idempotence".to_owned(), "true".to_owned());
+            self.set_default("request.required.acks", "all");
+        }
         self.set_default("request.required.acks", "1");
         self.set_default("message.timeout.ms", "30000");
         self.set_default("compression.type", "lz4");
@@ -69,15 +386,217 @@ impl Config {
     }
 }
 
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FileSinkConfig {
+    pub name: String,
+
+    /// Directory the files are written to, with one subdirectory per topic.
+    pub path: PathBuf,
+
+    /// Uncompressed size at which a file is closed and a new one is started.
+    #[serde(default = "default_max_file_bytes")]
+    pub max_file_bytes: u64,
+
+    /// Start a new file whenever events move into the next range of this many slots.
+    #[serde(default)]
+    pub slots_per_file: Option<u64>,
+
+    #[serde(default)]
+    pub compression: FileCompression,
+
+    /// zstd compression level, only used with `"compression": "zstd"`.
+    #[serde(default = "default_compression_level")]
+    pub compression_level: i32,
+
+    /// When written records are synced to disk.
+    #[serde(default)]
+    pub fsync: FsyncPolicy,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SocketSinkConfig {
+    pub name: String,
+
+    /// Path of the Unix domain socket readers connect to.
+    pub path: PathBuf,
+
+    /// Maximum number of readers connected at the same time.
+    #[serde(default = "default_max_readers")]
+    pub max_readers: usize,
+
+    /// Number of messages buffered per reader before it is disconnected as too slow.
+    #[serde(default = "default_reader_buffer")]
+    pub reader_buffer: usize,
+
+    /// How long a new reader has to send its subscription.
+    #[serde(default = "default_handshake_timeout_ms")]
+    pub handshake_timeout_ms: u64,
+
+    /// Permissions of the socket file in octal, like `"660"`. Only the owner may
+    /// connect by default.
+    #[serde(
+        default = "default_socket_mode",
+        deserialize_with = "deserialize_file_mode"
+    )]
+    pub mode: u32,
+}
+
+fn default_socket_mode() -> u32 {
+    0o600
+}
+
+fn deserialize_file_mode<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    let mode = String::deserialize(deserializer)?;
+    u32::from_str_radix(&mode, 8)
+        .ok()
+        .filter(|bits| *bits <= 0o777)
+        .ok_or_else(|| D::Error::custom(format!("invalid file mode \"{mode}\"")))
+}
+
+fn default_max_readers() -> usize {
+    16
+}
+
+fn default_reader_buffer() -> usize {
+    10_000
+}
+
+fn default_handshake_timeout_ms() -> u64 {
+    5_000
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FileCompression {
+    #[default]
+    None,
+    Zstd,
+}
+
+fn default_max_file_bytes() -> u64 {
+    256 * 1024 * 1024
+}
+
+fn default_compression_level() -> i32 {
+    3
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct PipelineConfig {
+    /// Number of worker threads encoding and publishing events.
+    pub workers: usize,
+    /// Maximum number of events buffered across all workers.
+    pub queue_capacity: usize,
+    /// What to do with new events when a worker queue is full.
+    pub overflow_policy: OverflowPolicy,
+    /// How often the pipeline logs its counters, including dropped and spilled
+    /// events. 0 only logs them on shutdown.
+    pub stats_interval_ms: u64,
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        Self {
+            workers: 2,
+            queue_capacity: 100_000,
+            overflow_policy: OverflowPolicy::Block,
+            stats_interval_ms: 60_000,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct ReloadConfig {
+    /// Watch the config file and swap in its filters when it changes.
+    pub enabled: bool,
+    /// How often the config file is checked for changes.
+    pub poll_interval_ms: u64,
+}
+
+impl Default for ReloadConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            poll_interval_ms: 2_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OverflowPolicy {
+    /// Wait on the calling thread until the queue has room.
+    Block,
+    /// Discard the event being published.
+    DropNewest,
+    /// Discard the oldest queued event to make room.
+    DropOldest,
+    /// Write the event to the local spool and replay it later.
+    SpillToDisk,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SpoolConfig {
+    /// Directory holding the spool segment files.
+    pub path: PathBuf,
+    /// Size at which the active segment is sealed and a new one is started.
+    #[serde(default = "default_segment_bytes")]
+    pub segment_bytes: u64,
+    /// Upper bound on the total size of all segments; records beyond it are dropped.
+    #[serde(default = "default_max_bytes")]
+    pub max_bytes: u64,
+    /// When spooled records are synced to disk.
+    #[serde(default)]
+    pub fsync: FsyncPolicy,
+    /// How often the drainer checks for spooled records and broker connectivity.
+    #[serde(default = "default_drain_interval_ms")]
+    pub drain_interval_ms: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FsyncPolicy {
+    /// Sync after every record.
+    Always,
+    /// Sync when a segment is sealed.
+    #[default]
+    OnRotate,
+    /// Leave syncing to the operating system.
+    Never,
+}
+
+fn default_segment_bytes() -> u64 {
+    64 * 1024 * 1024
+}
+
+fn default_max_bytes() -> u64 {
+    10 * 1024 * 1024 * 1024
+}
+
+fn default_drain_interval_ms() -> u64 {
+    1_000
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(deny_unknown_fields, default)]
 pub struct ConfigFilter {
+    /// Name of the sink matching events are published to.
+    pub sink: String,
     /// Kafka topic to send account updates to.
     pub update_account_topic: String,
     /// Kafka topic to send slot status updates to.
     pub slot_status_topic: String,
     /// Kafka topic to send transaction to.
     pub transaction_topic: String,
+    /// Kafka topic to send block metadata to.
+    pub block_metadata_topic: String,
+    /// Kafka topic to send ledger entries to.
+    pub entry_topic: String,
     /// List of programs to ignore.
     pub program_ignores: Vec<String>,
     /// List of accounts to ignore.
@@ -86,32 +605,423 @@ pub struct ConfigFilter {
     pub program_filters: Vec<String>,
     /// List of accounts to include
     pub account_filters: Vec<String>,
+    /// Predicates on account data that an account update must all satisfy.
+    pub account_data_filters: Vec<AccountDataFilter>,
+    /// Parts of the account data to publish instead of all of it.
+    pub data_slices: Vec<DataSlice>,
+    /// Publish transactions that reference at least one of these accounts.
+    pub account_include: Vec<String>,
+    /// Publish transactions that reference all of these accounts.
+    pub account_required: Vec<String>,
+    /// Skip transactions that reference any of these accounts.
+    pub account_exclude: Vec<String>,
+    /// Publish transactions signed by at least one of these accounts.
+    pub signer_filters: Vec<String>,
+    /// Publish transactions that write to at least one of these accounts.
+    pub writable_filters: Vec<String>,
+    /// Publish transactions that invoke one of these programs.
+    pub instruction_filters: Vec<InstructionFilter>,
+    /// Publish transactions with a log message matching one of these patterns.
+    pub log_include: Vec<LogPattern>,
+    /// Skip transactions with a log message matching any of these patterns.
+    pub log_exclude: Vec<LogPattern>,
+    /// How records are keyed, which decides the Kafka partition they land in.
+    pub partition_key: PartitionKey,
     /// Publish all accounts on startup.
     pub publish_all_accounts: bool,
     /// Publish vote transactions.
     pub include_vote_transactions: bool,
     /// Publish failed transactions.
     pub include_failed_transactions: bool,
-    /// Wrap all event message in a single message type.
+    /// How events are encoded. Defaults to `protobuf`, or `protobuf-wrapped` if
+    /// `wrap_messages` is set.
+    pub format: Option<MessageFormat>,
+    /// Wrap all event message in a single message type, same as `format: protobuf-wrapped`.
     pub wrap_messages: bool,
 }
 
 impl Default for ConfigFilter {
     fn default() -> Self {
         Self {
+            sink: DEFAULT_SINK.to_owned(),
             update_account_topic: "".to_owned(),
             slot_status_topic: "".to_owned(),
             transaction_topic: "".to_owned(),
+            block_metadata_topic: "".to_owned(),
+            entry_topic: "".to_owned(),
             program_ignores: Vec::new(),
             account_ignores: Vec::new(),
             program_filters: Vec::new(),
             account_filters: Vec::new(),
+            account_data_filters: Vec::new(),
+            data_slices: Vec::new(),
+            account_include: Vec::new(),
+            account_required: Vec::new(),
+            account_exclude: Vec::new(),
+            signer_filters: Vec::new(),
+            writable_filters: Vec::new(),
+            instruction_filters: Vec::new(),
+            log_include: Vec::new(),
+            log_exclude: Vec::new(),
+            partition_key: PartitionKey::default(),
             publish_all_accounts: false,
             include_vote_transactions: true,
             include_failed_transactions: true,
+            format: None,
             wrap_messages: false,
         }
     }
 }
 
-pub type Producer = ThreadedProducer<DefaultProducerContext>;
+impl ConfigFilter {
+    pub fn format(&self) -> MessageFormat {
+        self.format.unwrap_or(if self.wrap_messages {
+            MessageFormat::ProtobufWrapped
+        } else {
+            MessageFormat::Protobuf
+        })
+    }
+
+    /// Checks the settings that can be wrong without the file failing to parse,
+    /// returning every problem found.
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+
+        for (field, keys) in [
+            ("program_ignores", &self.program_ignores),
+            ("account_ignores", &self.account_ignores),
+            ("program_filters", &self.program_filters),
+            ("account_filters", &self.account_filters),
+            ("account_include", &self.account_include),
+            ("account_required", &self.account_required),
+            ("account_exclude", &self.account_exclude),
+            ("signer_filters", &self.signer_filters),
+            ("writable_filters", &self.writable_filters),
+        ] {
+            for (index, key) in keys.iter().enumerate() {
+                if let Err(e) = Pubkey::from_str(key) {
+                    problems.push(format!("{field}[{index}]: invalid pubkey {key:?}: {e}"));
+                }
+            }
+        }
+
+        for (index, data_filter) in self.account_data_filters.iter().enumerate() {
+            if let AccountDataFilter::Memcmp(memcmp) = data_filter
+                && let Err(e) = memcmp.decode()
+            {
+                problems.push(format!("account_data_filters[{index}]: {e}"));
+            }
+        }
+        for (index, instruction_filter) in self.instruction_filters.iter().enumerate() {
+            if let Err(e) = instruction_filter.decode_program() {
+                problems.push(format!("instruction_filters[{index}]: {e}"));
+            }
+            if let Err(e) = instruction_filter.decode_discriminators() {
+                problems.push(format!("instruction_filters[{index}]: {e}"));
+            }
+        }
+        for (field, patterns) in [
+            ("log_include", &self.log_include),
+            ("log_exclude", &self.log_exclude),
+        ] {
+            for (index, pattern) in patterns.iter().enumerate() {
+                if let LogPattern::Regex(regex) = pattern
+                    && let Err(e) = Regex::new(regex)
+                {
+                    problems.push(format!(
+                        "{field}[{index}]: invalid log pattern {regex:?}: {e}"
+                    ));
+                }
+            }
+        }
+
+        if let PartitionKey::Partition(partition) = self.partition_key
+            && partition < 0
+        {
+            problems.push(format!("partition_key: invalid partition {partition}"));
+        }
+
+        if self.wrap_messages
+            && let Some(format) = self.format
+            && format != MessageFormat::ProtobufWrapped
+        {
+            problems.push(format!(
+                "wrap_messages conflicts with format \"{}\"",
+                format.as_str()
+            ));
+        }
+
+        problems.extend(self.topic_problems());
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+
+    /// Settings that only narrow one kind of event are an error without its topic,
+    /// since they would otherwise be ignored without notice.
+    fn topic_problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if [
+            &self.update_account_topic,
+            &self.slot_status_topic,
+            &self.transaction_topic,
+            &self.block_metadata_topic,
+            &self.entry_topic,
+        ]
+        .iter()
+        .all(|topic| topic.is_empty())
+        {
+            problems.push("no topic is set".to_owned());
+        }
+
+        if self.update_account_topic.is_empty() {
+            for (field, set) in [
+                (
+                    "account_data_filters",
+                    !self.account_data_filters.is_empty(),
+                ),
+                ("data_slices", !self.data_slices.is_empty()),
+                ("publish_all_accounts", self.publish_all_accounts),
+            ] {
+                if set {
+                    problems.push(format!("{field} needs update_account_topic"));
+                }
+            }
+        }
+
+        if self.transaction_topic.is_empty() {
+            for (field, set) in [
+                ("account_include", !self.account_include.is_empty()),
+                ("account_required", !self.account_required.is_empty()),
+                ("account_exclude", !self.account_exclude.is_empty()),
+                ("signer_filters", !self.signer_filters.is_empty()),
+                ("writable_filters", !self.writable_filters.is_empty()),
+                ("instruction_filters", !self.instruction_filters.is_empty()),
+                ("log_include", !self.log_include.is_empty()),
+                ("log_exclude", !self.log_exclude.is_empty()),
+            ] {
+                if set {
+                    problems.push(format!("{field} needs transaction_topic"));
+                }
+            }
+        }
+        problems
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MessageFormat {
+    /// The bare protobuf event.
+    #[default]
+    Protobuf,
+    /// The event inside a protobuf `MessageWrapper`, so readers can tell its kind.
+    ProtobufWrapped,
+    /// A JSON object with the event under its kind, e.g. `{"account": {...}}`.
+    Json,
+    /// The bare protobuf event in Confluent's wire format, prefixed with the id
+    /// of the schema registered for the topic.
+    ConfluentProtobuf,
+}
+
+impl MessageFormat {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MessageFormat::Protobuf => "protobuf",
+            MessageFormat::ProtobufWrapped => "protobuf-wrapped",
+            MessageFormat::Json => "json",
+            MessageFormat::ConfluentProtobuf => "confluent-protobuf",
+        }
+    }
+}
+
+/// Picks the record key of each event. Strategies that do not apply to an event
+/// kind key it the default way.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PartitionKey {
+    /// Accounts by pubkey, transactions by signature and everything else by slot.
+    #[default]
+    Pubkey,
+    /// Accounts by owner program.
+    Owner,
+    /// Every event by slot.
+    Slot,
+    /// Transactions by fee payer.
+    FeePayer,
+    /// Every event to this partition, keyed the default way.
+    Partition(i32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DataSlice {
+    pub offset: usize,
+    pub length: usize,
+}
+
+/// An RPC style predicate on account data.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub enum AccountDataFilter {
+    /// Matches accounts whose data holds `bytes` at `offset`.
+    Memcmp(MemcmpFilter),
+    /// Matches accounts whose data is exactly this many bytes long.
+    DataSize(u64),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MemcmpFilter {
+    pub offset: usize,
+    pub bytes: String,
+    #[serde(default)]
+    pub encoding: BytesEncoding,
+}
+
+impl MemcmpFilter {
+    pub fn decode(&self) -> Result<Vec<u8>, String> {
+        let bytes = self
+            .encoding
+            .decode(&self.bytes)
+            .map_err(|e| format!("invalid memcmp bytes {:?}: {e}", self.bytes))?;
+        if bytes.is_empty() {
+            return Err("memcmp bytes must not be empty".to_owned());
+        }
+        Ok(bytes)
+    }
+}
+
+/// Matches instructions, top-level or inner, that invoke `program`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct InstructionFilter {
+    pub program: String,
+    /// Prefixes of the instruction data, one of which must match if any are given.
+    #[serde(default)]
+    pub discriminators: Vec<String>,
+    #[serde(default)]
+    pub encoding: BytesEncoding,
+    /// Also match instructions invoked through CPI.
+    #[serde(default = "default_include_inner")]
+    pub include_inner: bool,
+}
+
+impl InstructionFilter {
+    pub fn decode_program(&self) -> Result<[u8; 32], String> {
+        Pubkey::from_str(&self.program)
+            .map(|program| program.to_bytes())
+            .map_err(|e| format!("invalid instruction filter program {:?}: {e}", self.program))
+    }
+
+    pub fn decode_discriminators(&self) -> Result<Vec<Vec<u8>>, String> {
+        self.discriminators
+            .iter()
+            .map(|discriminator| {
+                self.encoding
+                    .decode(discriminator)
+                    .map_err(|e| format!("invalid discriminator {discriminator:?}: {e}"))
+            })
+            .collect()
+    }
+}
+
+fn default_include_inner() -> bool {
+    true
+}
+
+/// A pattern matched against each line of a transaction's log messages.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub enum LogPattern {
+    /// Matches lines containing this text.
+    Contains(String),
+    /// Matches lines this regular expression finds a match in.
+    Regex(String),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BytesEncoding {
+    #[default]
+    Base58,
+    Base64,
+}
+
+impl BytesEncoding {
+    pub fn decode(self, bytes: &str) -> Result<Vec<u8>, String> {
+        match self {
+            BytesEncoding::Base58 => bs58::decode(bytes).into_vec().map_err(|e| e.to_string()),
+            BytesEncoding::Base64 => BASE64_STANDARD.decode(bytes).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_account_data_filters() {
+        let filters: Vec<AccountDataFilter> = serde_json::from_str(
+            r#"[{"data-size": 165}, {"memcmp": {"offset": 32, "bytes": "AQI=", "encoding": "base64"}}]"#,
+        )
+        .unwrap();
+
+        assert!(matches!(filters[0], AccountDataFilter::DataSize(165)));
+        let AccountDataFilter::Memcmp(memcmp) = &filters[1] else {
+            panic!("expected a memcmp filter, got {:?}", filters[1]);
+        };
+        assert_eq!(memcmp.offset, 32);
+        assert_eq!(memcmp.encoding, BytesEncoding::Base64);
+        assert_eq!(memcmp.decode().unwrap(), [1, 2]);
+        assert!(serde_json::from_str::<AccountDataFilter>(r#"{"data_size": 165}"#).is_err());
+    }
+
+    #[test]
+    fn parses_partition_keys() {
+        let keys: Vec<PartitionKey> =
+            serde_json::from_str(r#"["pubkey", "owner", "slot", "fee-payer", {"partition": 3}]"#)
+                .unwrap();
+
+        assert_eq!(
+            keys,
+            [
+                PartitionKey::Pubkey,
+                PartitionKey::Owner,
+                PartitionKey::Slot,
+                PartitionKey::FeePayer,
+                PartitionKey::Partition(3),
+            ]
+        );
+        assert!(serde_json::from_str::<PartitionKey>(r#""fee_payer""#).is_err());
+    }
+
+    #[test]
+    fn parses_socket_modes() {
+        let parse = |json: &str| serde_json::from_str::<SocketSinkConfig>(json).map(|c| c.mode);
+        assert_eq!(
+            parse(r#"{"name": "local", "path": "/tmp/h.sock"}"#).unwrap(),
+            0o600
+        );
+        assert_eq!(
+            parse(r#"{"name": "local", "path": "/tmp/h.sock", "mode": "660"}"#).unwrap(),
+            0o660
+        );
+        assert!(parse(r#"{"name": "local", "path": "/tmp/h.sock", "mode": "8"}"#).is_err());
+        assert!(parse(r#"{"name": "local", "path": "/tmp/h.sock", "mode": "1777"}"#).is_err());
+    }
+
+    #[test]
+    fn parses_log_patterns() {
+        let patterns: Vec<LogPattern> =
+            serde_json::from_str(r#"[{"contains": "Swap"}, {"regex": "^Program data: "}]"#)
+                .unwrap();
+
+        assert!(matches!(&patterns[0], LogPattern::Contains(text) if text == "Swap"));
+        assert!(matches!(&patterns[1], LogPattern::Regex(regex) if regex == "^Program data: "));
+    }
+}
@@ -0,0 +1,438 @@
+use {
+    crate::{EventKind, MessageFormat, Record, Spool},
+    log::{error, info, warn},
+    rdkafka::{
+        ClientContext, Message,
+        error::{KafkaError, RDKafkaErrorCode},
+        producer::{DeliveryResult, ProducerContext},
+    },
+    solana_pubkey::Pubkey,
+    std::{
+        collections::HashMap,
+        sync::{Arc, Condvar, Mutex},
+        time::Duration,
+    },
+};
+
+/// Identifies what a Kafka record carries so delivery reports can be attributed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeliveryTag {
+    pub kind: EventKind,
+    pub format: MessageFormat,
+}
+
+impl DeliveryTag {
+    const WRAPPED: u8 = 0x01;
+    const JSON: u8 = 0x10;
+    const CONFLUENT: u8 = 0x20;
+
+    pub fn new(kind: EventKind, format: MessageFormat) -> Self {
+        Self { kind, format }
+    }
+
+    /// Whether the payload is a `MessageWrapper` and the key starts with a kind prefix.
+    pub fn is_wrapped(self) -> bool {
+        self.format == MessageFormat::ProtobufWrapped
+    }
+
+    pub fn to_byte(self) -> u8 {
+        let kind = match self.kind {
+            EventKind::Account => 0,
+            EventKind::Slot => 1,
+            EventKind::Transaction => 2,
+            EventKind::BlockMetadata => 3,
+            EventKind::Entry => 4,
+        };
+        let format = match self.format {
+            MessageFormat::Protobuf => 0,
+            MessageFormat::ProtobufWrapped => Self::WRAPPED,
+            MessageFormat::Json => Self::JSON,
+            MessageFormat::ConfluentProtobuf => Self::CONFLUENT,
+        };
+        (kind << 1) | format
+    }
+
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        let format = match byte & (Self::WRAPPED | Self::JSON | Self::CONFLUENT) {
+            0 => MessageFormat::Protobuf,
+            Self::WRAPPED => MessageFormat::ProtobufWrapped,
+            Self::JSON => MessageFormat::Json,
+            Self::CONFLUENT => MessageFormat::ConfluentProtobuf,
+            _ => return None,
+        };
+        let kind = match (byte & !(Self::JSON | Self::CONFLUENT)) >> 1 {
+            0 => EventKind::Account,
+            1 => EventKind::Slot,
+            2 => EventKind::Transaction,
+            3 => EventKind::BlockMetadata,
+            4 => EventKind::Entry,
+            _ => return None,
+        };
+        Some(Self { kind, format })
+    }
+
+    /// Renders a record key as the pubkey, signature or slot it was built from.
+    pub fn describe_key(self, key: Option<&[u8]>) -> String {
+        let Some(mut key) = key else {
+            return "<no key>".to_owned();
+        };
+        if self.is_wrapped() && !key.is_empty() {
+            key = &key[1..];
+        }
+
+        // Filters can key any event by slot and accounts by owner, so go by length.
+        if let Ok(bytes) = <[u8; 8]>::try_from(key) {
+            format!("slot {}", u64::from_le_bytes(bytes))
+        } else if let Ok(bytes) = <[u8; 32]>::try_from(key) {
+            Pubkey::new_from_array(bytes).to_string()
+        } else {
+            bs58::encode(key).into_string()
+        }
+    }
+}
+
+/// Travels with every Kafka record to its delivery report.
+#[derive(Debug)]
+pub struct Delivery {
+    pub tag: DeliveryTag,
+    pub slot: u64,
+    pub partition: Option<i32>,
+    /// Set for records replayed from a spool segment.
+    pub replay: Option<Arc<SegmentReplay>>,
+    /// Set for records sent in a slot transaction, which keeps them until it commits.
+    pub transactional: bool,
+}
+
+impl Delivery {
+    pub fn new(record: &Record<'_>) -> Box<Self> {
+        Box::new(Self {
+            tag: record.tag,
+            slot: record.slot,
+            partition: record.partition,
+            replay: None,
+            transactional: false,
+        })
+    }
+
+    pub fn transactional(record: &Record<'_>) -> Box<Self> {
+        Box::new(Self {
+            transactional: true,
+            ..*Self::new(record)
+        })
+    }
+}
+
+/// Tracks the delivery reports of a spool segment being replayed, so the segment is
+/// only deleted once every record in it reached Kafka.
+#[derive(Debug, Default)]
+pub struct SegmentReplay {
+    state: Mutex<ReplayState>,
+    settled: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct ReplayState {
+    outstanding: u64,
+    failed: u64,
+}
+
+impl SegmentReplay {
+    /// Called before a record is handed to the producer.
+    pub fn sent(&self) {
+        self.state.lock().unwrap().outstanding += 1;
+    }
+
+    /// Called if the producer refused a record, so no report will arrive for it.
+    pub fn unsent(&self) {
+        self.state.lock().unwrap().outstanding -= 1;
+        self.settled.notify_all();
+    }
+
+    /// Records the report of a sent record; `retry` means it has to be replayed again.
+    fn settle(&self, retry: bool) {
+        let mut state = self.state.lock().unwrap();
+        state.outstanding -= 1;
+        if retry {
+            state.failed += 1;
+        }
+        drop(state);
+        self.settled.notify_all();
+    }
+
+    /// Waits for the reports of every record sent so far. Returns how many records
+    /// have to be replayed again, or None if reports are still outstanding after `timeout`.
+    pub fn wait(&self, timeout: Duration) -> Option<u64> {
+        let (state, _) = self
+            .settled
+            .wait_timeout_while(self.state.lock().unwrap(), timeout, |state| {
+                state.outstanding > 0
+            })
+            .unwrap();
+        (state.outstanding == 0).then_some(state.failed)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DeliveryCounts {
+    pub delivered: u64,
+    pub failed: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct DeliveryStats {
+    counts: Mutex<HashMap<(String, EventKind), DeliveryCounts>>,
+}
+
+impl DeliveryStats {
+    fn record(&self, topic: &str, kind: EventKind, delivered: bool) {
+        let mut counts = self.counts.lock().unwrap();
+        let entry = counts.entry((topic.to_owned(), kind)).or_default();
+        if delivered {
+            entry.delivered += 1;
+        } else {
+            entry.failed += 1;
+        }
+    }
+
+    /// Returns the delivery counts per topic and event kind.
+    pub fn snapshot(&self) -> Vec<(String, EventKind, DeliveryCounts)> {
+        let mut snapshot: Vec<_> = self
+            .counts
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|((topic, kind), counts)| (topic.clone(), *kind, *counts))
+            .collect();
+        snapshot.sort_by(|a, b| (&a.0, a.1.as_str()).cmp(&(&b.0, b.1.as_str())));
+        snapshot
+    }
+
+    pub fn log_summary(&self) {
+        for (topic, kind, counts) in self.snapshot() {
+            info!(
+                "Delivery summary for {} events on topic {}: delivered={} failed={}",
+                kind.as_str(),
+                topic,
+                counts.delivered,
+                counts.failed
+            );
+        }
+    }
+}
+
+/// Producer context that tracks delivery reports for every record sent by the plugin.
+///
+/// Records that librdkafka gives up on while the brokers are unreachable go back to
+/// the sink's spool to be replayed, or stay in their segment if they were being
+/// replayed already. Records rejected for any other reason are logged with their key
+/// and, if a dead-letter store is configured, written to it so they can be inspected
+/// or replayed by hand. Records sent in a slot transaction are left to it, since
+/// they are kept until the transaction commits.
+pub struct HeimdallProducerContext {
+    stats: DeliveryStats,
+    spool: Option<Arc<Spool>>,
+    dead_letter: Option<Spool>,
+}
+
+impl HeimdallProducerContext {
+    pub fn new(spool: Option<Arc<Spool>>, dead_letter: Option<Spool>) -> Self {
+        Self {
+            stats: DeliveryStats::default(),
+            spool,
+            dead_letter,
+        }
+    }
+
+    pub fn stats(&self) -> &DeliveryStats {
+        &self.stats
+    }
+
+    /// Whether the record failed only because the brokers were unreachable or too
+    /// slow, so producing it again later can succeed.
+    fn is_retriable(error: &KafkaError) -> bool {
+        matches!(
+            error.rdkafka_error_code(),
+            Some(
+                RDKafkaErrorCode::MessageTimedOut
+                    | RDKafkaErrorCode::BrokerTransportFailure
+                    | RDKafkaErrorCode::AllBrokersDown
+                    | RDKafkaErrorCode::Resolve
+                    | RDKafkaErrorCode::OperationTimedOut
+                    | RDKafkaErrorCode::RequestTimedOut
+                    | RDKafkaErrorCode::NetworkException
+                    | RDKafkaErrorCode::LeaderNotAvailable
+                    | RDKafkaErrorCode::NotLeaderForPartition
+                    | RDKafkaErrorCode::NotEnoughReplicas
+                    | RDKafkaErrorCode::NotEnoughReplicasAfterAppend
+            )
+        )
+    }
+
+    /// Writes a record that will not be delivered to the dead-letter store, if any.
+    pub fn dead_letter(&self, record: &Record<'_>) {
+        if let Some(dead_letter) = &self.dead_letter
+            && let Err(e) = dead_letter.append(record)
+        {
+            error!("Failed to write undelivered record to dead-letter store: {e:?}");
+        }
+    }
+}
+
+impl ClientContext for HeimdallProducerContext {}
+
+impl ProducerContext for HeimdallProducerContext {
+    type DeliveryOpaque = Box<Delivery>;
+
+    fn delivery(
+        &self,
+        delivery_result: &DeliveryResult<'_>,
+        delivery_opaque: Self::DeliveryOpaque,
+    ) {
+        let Delivery {
+            tag,
+            slot,
+            partition,
+            replay,
+            transactional,
+        } = *delivery_opaque;
+
+        let (error, message) = match delivery_result {
+            Ok(message) => {
+                self.stats.record(message.topic(), tag.kind, true);
+                if let Some(replay) = replay {
+                    replay.settle(false);
+                }
+                return;
+            }
+            Err((error, message)) => (error, message),
+        };
+        self.stats.record(message.topic(), tag.kind, false);
+        let record = Record {
+            topic: message.topic(),
+            tag,
+            slot,
+            key: message.key().unwrap_or_default(),
+            partition,
+            payload: message.payload().unwrap_or_default(),
+            event: None,
+        };
+        let key = tag.describe_key(message.key());
+
+        if transactional {
+            // The transaction fails to commit and its records are sent again.
+            warn!(
+                "Failed to deliver {} event {} to topic {} in transaction of slot {}: {:?}",
+                tag.kind.as_str(),
+                key,
+                record.topic,
+                slot,
+                error
+            );
+            return;
+        }
+
+        if Self::is_retriable(error) {
+            if let Some(replay) = replay {
+                warn!(
+                    "Failed to deliver replayed {} event {} to topic {}, keeping its segment: {:?}",
+                    tag.kind.as_str(),
+                    key,
+                    record.topic,
+                    error
+                );
+                replay.settle(true);
+                return;
+            }
+            if let Some(spool) = &self.spool {
+                match spool.append(&record) {
+                    Ok(()) => {
+                        warn!(
+                            "Spooled {} event {} for topic {} after delivery failed: {:?}",
+                            tag.kind.as_str(),
+                            key,
+                            record.topic,
+                            error
+                        );
+                        return;
+                    }
+                    Err(e) => error!("Failed to spool undelivered record: {e:?}"),
+                }
+            }
+        } else if let Some(replay) = replay {
+            // Replaying the segment again would fail the same way.
+            replay.settle(false);
+        }
+
+        error!(
+            "Failed to deliver {} event {} to topic {}: {:?}",
+            tag.kind.as_str(),
+            key,
+            record.topic,
+            error
+        );
+        self.dead_letter(&record);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, std::collections::HashSet};
+
+    const KINDS: [EventKind; 5] = [
+        EventKind::Account,
+        EventKind::Slot,
+        EventKind::Transaction,
+        EventKind::BlockMetadata,
+        EventKind::Entry,
+    ];
+    const FORMATS: [MessageFormat; 4] = [
+        MessageFormat::Protobuf,
+        MessageFormat::ProtobufWrapped,
+        MessageFormat::Json,
+        MessageFormat::ConfluentProtobuf,
+    ];
+
+    #[test]
+    fn tags_round_trip_through_bytes() {
+        let mut bytes = HashSet::new();
+        for kind in KINDS {
+            for format in FORMATS {
+                let tag = DeliveryTag::new(kind, format);
+                let byte = tag.to_byte();
+                assert!(bytes.insert(byte), "{tag:?} shares byte {byte:#04x}");
+                assert_eq!(DeliveryTag::from_byte(byte), Some(tag));
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_bytes() {
+        // Two formats at once.
+        assert_eq!(DeliveryTag::from_byte(0x11), None);
+        assert_eq!(DeliveryTag::from_byte(0x30), None);
+        // Kinds past the last one.
+        assert_eq!(DeliveryTag::from_byte(5 << 1), None);
+        assert_eq!(DeliveryTag::from_byte(0x40), None);
+    }
+
+    #[test]
+    fn describes_keys() {
+        let protobuf = DeliveryTag::new(EventKind::Slot, MessageFormat::Protobuf);
+        let wrapped = DeliveryTag::new(EventKind::Slot, MessageFormat::ProtobufWrapped);
+        let pubkey = Pubkey::new_unique();
+
+        assert_eq!(protobuf.describe_key(None), "<no key>");
+        assert_eq!(protobuf.describe_key(Some(&7u64.to_le_bytes())), "slot 7");
+        let mut key = vec![b'S'];
+        key.extend_from_slice(&7u64.to_le_bytes());
+        assert_eq!(wrapped.describe_key(Some(&key)), "slot 7");
+        assert_eq!(
+            protobuf.describe_key(Some(pubkey.as_ref())),
+            pubkey.to_string()
+        );
+        assert_eq!(
+            protobuf.describe_key(Some(&[1; 64])),
+            bs58::encode([1; 64]).into_string()
+        );
+    }
+}
@@ -0,0 +1,609 @@
+use {
+    crate::{
+        MessageFormat, OverflowPolicy, PartitionKey, PipelineConfig, Publisher, SlotStatus,
+        message_wrapper::EventMessage,
+    },
+    log::{debug, error, info, warn},
+    std::{
+        collections::{HashMap, VecDeque, hash_map::DefaultHasher},
+        fmt::{Display, Formatter},
+        hash::{Hash, Hasher},
+        sync::{
+            Arc, Condvar, Mutex,
+            atomic::{AtomicU64, Ordering},
+            mpsc::{self, RecvTimeoutError, Sender},
+        },
+        thread::{self, JoinHandle},
+        time::Duration,
+    },
+};
+
+#[derive(Debug)]
+pub enum DispatchError {
+    Closed,
+}
+
+impl Display for DispatchError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DispatchError::Closed => write!(f, "publish pipeline is shut down"),
+        }
+    }
+}
+
+impl std::error::Error for DispatchError {}
+
+#[derive(Debug, Default)]
+pub struct DispatcherStats {
+    pub enqueued: AtomicU64,
+    pub published: AtomicU64,
+    pub failed: AtomicU64,
+    pub dropped_newest: AtomicU64,
+    pub dropped_oldest: AtomicU64,
+    pub spilled: AtomicU64,
+    pub dropped_spill: AtomicU64,
+}
+
+impl DispatcherStats {
+    pub fn dropped(&self) -> u64 {
+        self.dropped_newest.load(Ordering::Relaxed)
+            + self.dropped_oldest.load(Ordering::Relaxed)
+            + self.dropped_spill.load(Ordering::Relaxed)
+    }
+}
+
+impl Display for DispatcherStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "enqueued={} published={} failed={} spilled={} dropped_newest={} dropped_oldest={} dropped_spill={}",
+            self.enqueued.load(Ordering::Relaxed),
+            self.published.load(Ordering::Relaxed),
+            self.failed.load(Ordering::Relaxed),
+            self.spilled.load(Ordering::Relaxed),
+            self.dropped_newest.load(Ordering::Relaxed),
+            self.dropped_oldest.load(Ordering::Relaxed),
+            self.dropped_spill.load(Ordering::Relaxed),
+        )
+    }
+}
+
+struct Job {
+    sink: String,
+    message: EventMessage,
+    format: MessageFormat,
+    partition_key: PartitionKey,
+    topic: String,
+}
+
+#[allow(clippy::large_enum_variant)]
+enum Task {
+    Publish(Job),
+    /// Events taken out of a full queue, written to the sinks' spools by the worker.
+    Spill(Vec<Job>),
+    /// Queued on every worker; the last one to reach it reports the status to the sinks.
+    SlotStatus {
+        slot: u64,
+        status: SlotStatus,
+    },
+}
+
+/// Counts the workers that reached each queued slot status.
+struct SlotBarrier {
+    arrivals: Mutex<HashMap<(u64, SlotStatus), usize>>,
+    workers: usize,
+}
+
+impl SlotBarrier {
+    /// Returns true for the last worker to arrive.
+    fn arrive(&self, slot: u64, status: SlotStatus) -> bool {
+        let mut arrivals = self.arrivals.lock().unwrap();
+        let arrived = arrivals.entry((slot, status)).or_default();
+        *arrived += 1;
+        if *arrived < self.workers {
+            return false;
+        }
+        arrivals.remove(&(slot, status));
+        true
+    }
+}
+
+struct QueueState {
+    jobs: VecDeque<Task>,
+    /// Events to spill, older than every queued task, so the worker takes them first.
+    spill: Vec<Job>,
+    closed: bool,
+}
+
+struct BoundedQueue {
+    state: Mutex<QueueState>,
+    not_empty: Condvar,
+    not_full: Condvar,
+    capacity: usize,
+}
+
+impl BoundedQueue {
+    fn new(capacity: usize) -> Self {
+        Self {
+            state: Mutex::new(QueueState {
+                jobs: VecDeque::with_capacity(capacity),
+                spill: Vec::new(),
+                closed: false,
+            }),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+            capacity,
+        }
+    }
+
+    /// Queues a job. With `SpillToDisk`, the events of a full queue are handed to the
+    /// worker to spill, followed by the job, so the caller never waits on disk I/O.
+    /// If the worker has not taken the previous spill yet, the job is dropped.
+    fn push(
+        &self,
+        job: Job,
+        policy: OverflowPolicy,
+        stats: &DispatcherStats,
+    ) -> Result<(), DispatchError> {
+        let mut state = self.state.lock().unwrap();
+        if state.jobs.len() >= self.capacity {
+            match policy {
+                OverflowPolicy::Block => {
+                    state = self
+                        .not_full
+                        .wait_while(state, |s| s.jobs.len() >= self.capacity && !s.closed)
+                        .unwrap();
+                }
+                OverflowPolicy::DropNewest => {
+                    stats.dropped_newest.fetch_add(1, Ordering::Relaxed);
+                    debug!("Publish queue full, dropping event for topic {}", job.topic);
+                    return Ok(());
+                }
+                OverflowPolicy::SpillToDisk => {
+                    if state.closed {
+                        return Err(DispatchError::Closed);
+                    }
+                    if state.spill.len() >= self.capacity {
+                        stats.dropped_spill.fetch_add(1, Ordering::Relaxed);
+                        debug!(
+                            "Publish queue full while spilling, dropping event for topic {}",
+                            job.topic
+                        );
+                        return Ok(());
+                    }
+                    // Slot statuses stay queued behind the spilled events.
+                    let (queued, statuses) = std::mem::take(&mut state.jobs)
+                        .into_iter()
+                        .partition::<VecDeque<_>, _>(|task| matches!(task, Task::Publish(_)));
+                    state.jobs = statuses;
+                    let queued = queued.into_iter().filter_map(|task| match task {
+                        Task::Publish(queued) => Some(queued),
+                        _ => None,
+                    });
+                    state.spill.extend(queued);
+                    state.spill.push(job);
+                    drop(state);
+                    self.not_empty.notify_one();
+                    return Ok(());
+                }
+                OverflowPolicy::DropOldest => {
+                    let oldest = state
+                        .jobs
+                        .iter()
+                        .position(|task| matches!(task, Task::Publish(_)));
+                    if let Some(Task::Publish(oldest)) =
+                        oldest.and_then(|index| state.jobs.remove(index))
+                    {
+                        stats.dropped_oldest.fetch_add(1, Ordering::Relaxed);
+                        debug!(
+                            "Publish queue full, dropping oldest event for topic {}",
+                            oldest.topic
+                        );
+                    }
+                }
+            }
+        }
+
+        if state.closed {
+            return Err(DispatchError::Closed);
+        }
+
+        state.jobs.push_back(Task::Publish(job));
+        stats.enqueued.fetch_add(1, Ordering::Relaxed);
+        drop(state);
+        self.not_empty.notify_one();
+        Ok(())
+    }
+
+    /// Queues a slot status behind the jobs already queued. Statuses are never
+    /// dropped, so they may exceed the capacity.
+    fn push_slot_status(&self, slot: u64, status: SlotStatus) -> Result<(), DispatchError> {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            return Err(DispatchError::Closed);
+        }
+        state.jobs.push_back(Task::SlotStatus { slot, status });
+        drop(state);
+        self.not_empty.notify_one();
+        Ok(())
+    }
+
+    fn pop(&self) -> Option<Task> {
+        let mut state = self
+            .not_empty
+            .wait_while(self.state.lock().unwrap(), |s| {
+                s.jobs.is_empty() && s.spill.is_empty() && !s.closed
+            })
+            .unwrap();
+        let task = if state.spill.is_empty() {
+            state.jobs.pop_front()
+        } else {
+            Some(Task::Spill(std::mem::take(&mut state.spill)))
+        };
+        drop(state);
+        self.not_full.notify_one();
+        task
+    }
+
+    fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.not_empty.notify_all();
+        self.not_full.notify_all();
+    }
+}
+
+struct Worker {
+    queue: Arc<BoundedQueue>,
+    handle: Option<JoinHandle<()>>,
+}
+
+/// Logs the pipeline counters periodically, warning when events were dropped or spilled.
+struct Reporter {
+    stop: Sender<()>,
+    handle: JoinHandle<()>,
+}
+
+impl Reporter {
+    fn spawn(stats: Arc<DispatcherStats>, interval: Duration) -> Self {
+        let (stop, stopped) = mpsc::channel();
+        let handle = thread::Builder::new()
+            .name("heimdallStats".to_owned())
+            .spawn(move || {
+                let mut reported = (0, 0);
+                while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                    let dropped = stats.dropped();
+                    let spilled = stats.spilled.load(Ordering::Relaxed);
+                    if (dropped, spilled) != reported {
+                        warn!(
+                            "Publish queue overflowed, {} events dropped and {} spilled in the last {:?}",
+                            dropped - reported.0,
+                            spilled - reported.1,
+                            interval
+                        );
+                        reported = (dropped, spilled);
+                    }
+                    info!("Publish pipeline: {stats}");
+                }
+            })
+            .expect("failed to spawn pipeline stats reporter");
+        Self { stop, handle }
+    }
+
+    fn stop(self) {
+        drop(self.stop);
+        if self.handle.join().is_err() {
+            error!("Pipeline stats reporter panicked during shutdown");
+        }
+    }
+}
+
+/// Moves encoding and publishing off the validator threads.
+///
+/// Events are sharded across workers by their record key, so updates for the same
+/// account, transaction or slot are always published in the order they arrived.
+pub struct Dispatcher {
+    workers: Vec<Worker>,
+    overflow_policy: OverflowPolicy,
+    stats: Arc<DispatcherStats>,
+    reporter: Option<Reporter>,
+}
+
+struct WorkerContext {
+    publisher: Arc<Publisher>,
+    stats: Arc<DispatcherStats>,
+    barrier: Arc<SlotBarrier>,
+}
+
+impl Dispatcher {
+    pub fn new(publisher: Publisher, config: &PipelineConfig) -> Self {
+        let publisher = Arc::new(publisher);
+        let stats = Arc::new(DispatcherStats::default());
+        let num_workers = config.workers.max(1);
+        let capacity = (config.queue_capacity / num_workers).max(1);
+        let barrier = Arc::new(SlotBarrier {
+            arrivals: Mutex::new(HashMap::new()),
+            workers: num_workers,
+        });
+
+        let workers = (0..num_workers)
+            .map(|i| {
+                let queue = Arc::new(BoundedQueue::new(capacity));
+                let handle = {
+                    let queue = Arc::clone(&queue);
+                    let context = WorkerContext {
+                        publisher: Arc::clone(&publisher),
+                        stats: Arc::clone(&stats),
+                        barrier: Arc::clone(&barrier),
+                    };
+                    thread::Builder::new()
+                        .name(format!("heimdallPub{i:02}"))
+                        .spawn(move || Self::run_worker(&queue, &context))
+                        .expect("failed to spawn publish worker")
+                };
+                Worker {
+                    queue,
+                    handle: Some(handle),
+                }
+            })
+            .collect();
+
+        let reporter = (config.stats_interval_ms > 0).then(|| {
+            Reporter::spawn(
+                Arc::clone(&stats),
+                Duration::from_millis(config.stats_interval_ms),
+            )
+        });
+
+        Self {
+            workers,
+            overflow_policy: config.overflow_policy,
+            stats,
+            reporter,
+        }
+    }
+
+    pub fn publish(
+        &self,
+        sink: &str,
+        message: EventMessage,
+        format: MessageFormat,
+        partition_key: PartitionKey,
+        topic: &str,
+    ) -> Result<(), DispatchError> {
+        let shard = Self::shard_of(&message, partition_key) as usize % self.workers.len();
+        let job = Job {
+            sink: sink.to_owned(),
+            message,
+            format,
+            partition_key,
+            topic: topic.to_owned(),
+        };
+        self.workers[shard]
+            .queue
+            .push(job, self.overflow_policy, &self.stats)
+    }
+
+    /// Reports a slot status to the sinks after every event published before it.
+    pub fn slot_status(&self, slot: u64, status: SlotStatus) -> Result<(), DispatchError> {
+        for worker in &self.workers {
+            worker.queue.push_slot_status(slot, status)?;
+        }
+        Ok(())
+    }
+
+    pub fn stats(&self) -> &DispatcherStats {
+        &self.stats
+    }
+
+    /// Events that share a record key share a worker, so they reach the sink in the
+    /// order they were published.
+    fn shard_of(message: &EventMessage, partition_key: PartitionKey) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        match partition_key {
+            PartitionKey::Partition(partition) => partition.hash(&mut hasher),
+            _ => Publisher::record_key(message, partition_key).hash(&mut hasher),
+        }
+        hasher.finish()
+    }
+
+    fn run_worker(queue: &BoundedQueue, context: &WorkerContext) {
+        let WorkerContext {
+            publisher,
+            stats,
+            barrier,
+        } = context;
+        while let Some(task) = queue.pop() {
+            match task {
+                Task::Publish(Job {
+                    sink,
+                    message,
+                    format,
+                    partition_key,
+                    topic,
+                }) => {
+                    match publisher.publish(&sink, message, format, partition_key, &topic) {
+                        Ok(()) => stats.published.fetch_add(1, Ordering::Relaxed),
+                        Err(_) => stats.failed.fetch_add(1, Ordering::Relaxed),
+                    };
+                }
+                Task::Spill(jobs) => {
+                    for job in jobs {
+                        match publisher.spill(
+                            &job.sink,
+                            job.message,
+                            job.format,
+                            job.partition_key,
+                            &job.topic,
+                        ) {
+                            Ok(()) => stats.spilled.fetch_add(1, Ordering::Relaxed),
+                            Err(e) => {
+                                debug!("Failed to spill event for topic {}: {:?}", job.topic, e);
+                                stats.dropped_spill.fetch_add(1, Ordering::Relaxed)
+                            }
+                        };
+                    }
+                }
+                Task::SlotStatus { slot, status } => {
+                    if barrier.arrive(slot, status)
+                        && let Err(e) = publisher.slot_status(slot, status)
+                    {
+                        error!("Failed to handle status {status:?} of slot {slot}: {e:?}");
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Dispatcher {
+    fn drop(&mut self) {
+        debug!("Shutting down Heimdall publish pipeline");
+        if let Some(reporter) = self.reporter.take() {
+            reporter.stop();
+        }
+        for worker in &self.workers {
+            worker.queue.close();
+        }
+        for worker in &mut self.workers {
+            if let Some(handle) = worker.handle.take()
+                && handle.join().is_err()
+            {
+                error!("Publish worker panicked during shutdown");
+            }
+        }
+
+        info!("Publish pipeline stopped: {}", self.stats);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::SlotStatusEvent};
+
+    fn job(slot: u64) -> Job {
+        Job {
+            sink: "kafka".to_owned(),
+            message: EventMessage::Slot(SlotStatusEvent {
+                slot,
+                ..Default::default()
+            }),
+            format: MessageFormat::Protobuf,
+            partition_key: PartitionKey::Slot,
+            topic: "slots".to_owned(),
+        }
+    }
+
+    fn slot(job: &Job) -> u64 {
+        match &job.message {
+            EventMessage::Slot(event) => event.slot,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Closes the queue and returns what is left in it, spilled events by slot.
+    fn drain(queue: &BoundedQueue) -> Vec<(&'static str, Vec<u64>)> {
+        queue.close();
+        std::iter::from_fn(|| queue.pop())
+            .map(|task| match task {
+                Task::Publish(job) => ("publish", vec![slot(&job)]),
+                Task::Spill(jobs) => ("spill", jobs.iter().map(slot).collect()),
+                Task::SlotStatus { slot, .. } => ("status", vec![slot]),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn block_waits_for_room() {
+        let queue = BoundedQueue::new(1);
+        let stats = DispatcherStats::default();
+        queue.push(job(1), OverflowPolicy::Block, &stats).unwrap();
+
+        thread::scope(|scope| {
+            let blocked = scope.spawn(|| queue.push(job(2), OverflowPolicy::Block, &stats));
+            thread::sleep(std::time::Duration::from_millis(50));
+            assert!(!blocked.is_finished());
+            assert!(matches!(queue.pop(), Some(Task::Publish(job)) if slot(&job) == 1));
+            blocked.join().unwrap().unwrap();
+        });
+
+        assert_eq!(drain(&queue), [("publish", vec![2])]);
+        assert_eq!(stats.dropped(), 0);
+    }
+
+    #[test]
+    fn drop_newest_discards_the_new_event() {
+        let queue = BoundedQueue::new(2);
+        let stats = DispatcherStats::default();
+        for slot in 1..=3 {
+            queue
+                .push(job(slot), OverflowPolicy::DropNewest, &stats)
+                .unwrap();
+        }
+
+        assert_eq!(drain(&queue), [("publish", vec![1]), ("publish", vec![2])]);
+        assert_eq!(stats.dropped_newest.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn drop_oldest_keeps_slot_statuses() {
+        let queue = BoundedQueue::new(3);
+        let stats = DispatcherStats::default();
+        queue.push_slot_status(7, SlotStatus::Confirmed).unwrap();
+        for slot in 1..=3 {
+            queue
+                .push(job(slot), OverflowPolicy::DropOldest, &stats)
+                .unwrap();
+        }
+
+        assert_eq!(
+            drain(&queue),
+            [
+                ("status", vec![7]),
+                ("publish", vec![2]),
+                ("publish", vec![3])
+            ]
+        );
+        assert_eq!(stats.dropped_oldest.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn spill_to_disk_hands_queued_events_to_the_worker_in_order() {
+        let queue = BoundedQueue::new(2);
+        let stats = DispatcherStats::default();
+        queue
+            .push(job(1), OverflowPolicy::SpillToDisk, &stats)
+            .unwrap();
+        queue.push_slot_status(7, SlotStatus::Confirmed).unwrap();
+        queue
+            .push(job(2), OverflowPolicy::SpillToDisk, &stats)
+            .unwrap();
+        // Queued after the spill, so published after it.
+        queue
+            .push(job(3), OverflowPolicy::SpillToDisk, &stats)
+            .unwrap();
+
+        assert_eq!(
+            drain(&queue),
+            [
+                ("spill", vec![1, 2]),
+                ("status", vec![7]),
+                ("publish", vec![3])
+            ]
+        );
+        assert_eq!(stats.dropped(), 0);
+    }
+
+    #[test]
+    fn spill_to_disk_drops_events_while_the_worker_is_behind() {
+        let queue = BoundedQueue::new(1);
+        let stats = DispatcherStats::default();
+        for slot in 1..=4 {
+            queue
+                .push(job(slot), OverflowPolicy::SpillToDisk, &stats)
+                .unwrap();
+        }
+
+        assert_eq!(drain(&queue), [("spill", vec![1, 2]), ("publish", vec![3])]);
+        assert_eq!(stats.dropped_spill.load(Ordering::Relaxed), 1);
+    }
+}
@@ -1,56 +1,194 @@
 use {
-    crate::ConfigFilter,
+    crate::{
+        AccountDataFilter, AccountDataSlice, ConfigFilter, DataSlice, InstructionFilter,
+        InvokedInstruction, LogPattern, MessageFormat, PartitionKey, TransactionAccount,
+        UpdateAccountEvent,
+        message_wrapper::EventMessage::{self, Account, BlockMetadata, Entry, Slot, Transaction},
+    },
+    regex::Regex,
     solana_pubkey::Pubkey,
-    std::{collections::HashSet, str::FromStr},
+    std::{collections::HashSet, fmt::Debug, str::FromStr},
 };
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvocationFilter {
+    /// None if the configured program or discriminators could not be decoded, so
+    /// the filter matches nothing.
+    pub program: Option<[u8; 32]>,
+    pub discriminators: Vec<Vec<u8>>,
+    pub include_inner: bool,
+}
+
+impl InvocationFilter {
+    fn new(config: &InstructionFilter) -> Self {
+        match (config.decode_program(), config.decode_discriminators()) {
+            (Ok(program), Ok(discriminators)) => Self {
+                program: Some(program),
+                discriminators,
+                include_inner: config.include_inner,
+            },
+            _ => Self {
+                program: None,
+                discriminators: Vec::new(),
+                include_inner: config.include_inner,
+            },
+        }
+    }
+
+    pub fn matches(&self, instruction: &InvokedInstruction<'_>) -> bool {
+        self.program
+            .is_some_and(|program| instruction.program == program.as_slice())
+            && (self.include_inner || !instruction.inner)
+            && (self.discriminators.is_empty()
+                || self
+                    .discriminators
+                    .iter()
+                    .any(|discriminator| instruction.data.starts_with(discriminator)))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum LogFilter {
+    Contains(String),
+    Regex(Regex),
+    /// A regular expression that failed to compile, matches nothing.
+    InvalidRegex(String),
+}
+
+impl LogFilter {
+    fn new(config: &LogPattern) -> Self {
+        match config {
+            LogPattern::Contains(text) => LogFilter::Contains(text.clone()),
+            LogPattern::Regex(regex) => match Regex::new(regex) {
+                Ok(regex) => LogFilter::Regex(regex),
+                Err(_) => LogFilter::InvalidRegex(regex.clone()),
+            },
+        }
+    }
+
+    pub fn matches(&self, line: &str) -> bool {
+        match self {
+            LogFilter::Contains(text) => line.contains(text.as_str()),
+            LogFilter::Regex(regex) => regex.is_match(line),
+            LogFilter::InvalidRegex(_) => false,
+        }
+    }
+}
+
+impl PartialEq for LogFilter {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (LogFilter::Contains(a), LogFilter::Contains(b)) => a == b,
+            (LogFilter::Regex(a), LogFilter::Regex(b)) => a.as_str() == b.as_str(),
+            (LogFilter::InvalidRegex(a), LogFilter::InvalidRegex(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DataFilter {
+    Memcmp {
+        offset: usize,
+        bytes: Vec<u8>,
+    },
+    DataSize(u64),
+    /// A memcmp whose bytes could not be decoded, it matches nothing.
+    InvalidMemcmp,
+}
+
+impl DataFilter {
+    fn new(config: &AccountDataFilter) -> Self {
+        match config {
+            AccountDataFilter::Memcmp(memcmp) => match memcmp.decode() {
+                Ok(bytes) => DataFilter::Memcmp {
+                    offset: memcmp.offset,
+                    bytes,
+                },
+                Err(_error) => DataFilter::InvalidMemcmp,
+            },
+            AccountDataFilter::DataSize(size) => DataFilter::DataSize(*size),
+        }
+    }
+
+    pub fn matches(&self, data: &[u8]) -> bool {
+        match self {
+            DataFilter::Memcmp { offset, bytes } => data
+                .get(*offset..)
+                .is_some_and(|data| data.starts_with(bytes)),
+            DataFilter::DataSize(size) => data.len() as u64 == *size,
+            DataFilter::InvalidMemcmp => false,
+        }
+    }
+}
+
 pub struct Filter {
+    pub sink: String,
     pub publish_all_accounts: bool,
     pub program_ignores: HashSet<[u8; 32]>,
     pub account_ignores: HashSet<[u8; 32]>,
     pub program_filters: HashSet<[u8; 32]>,
     pub account_filters: HashSet<[u8; 32]>,
+    pub account_data_filters: Vec<DataFilter>,
+    pub data_slices: Vec<DataSlice>,
+    pub account_include: HashSet<[u8; 32]>,
+    pub account_required: HashSet<[u8; 32]>,
+    pub account_exclude: HashSet<[u8; 32]>,
+    pub signer_filters: HashSet<[u8; 32]>,
+    pub writable_filters: HashSet<[u8; 32]>,
+    pub instruction_filters: Vec<InvocationFilter>,
+    pub log_include: Vec<LogFilter>,
+    pub log_exclude: Vec<LogFilter>,
+    pub partition_key: PartitionKey,
     pub include_vote_transactions: bool,
     pub include_failed_transactions: bool,
     pub update_account_topic: String,
     pub slot_status_topic: String,
     pub transaction_topic: String,
+    pub block_metadata_topic: String,
+    pub entry_topic: String,
 
-    pub wrap_messages: bool,
+    pub format: MessageFormat,
 }
 
 impl Filter {
     pub fn new(config: &ConfigFilter) -> Self {
         Self {
+            sink: config.sink.clone(),
             publish_all_accounts: config.publish_all_accounts,
-            program_ignores: config
-                .program_ignores
+            program_ignores: Self::parse_pubkeys(&config.program_ignores),
+            program_filters: Self::parse_pubkeys(&config.program_filters),
+            account_ignores: Self::parse_pubkeys(&config.account_ignores),
+            account_filters: Self::parse_pubkeys(&config.account_filters),
+            account_data_filters: config
+                .account_data_filters
                 .iter()
-                .flat_map(|p| Pubkey::from_str(p).ok().map(|p| p.to_bytes()))
+                .map(DataFilter::new)
                 .collect(),
-            program_filters: config
-                .program_filters
+            data_slices: config.data_slices.clone(),
+            account_include: Self::parse_pubkeys(&config.account_include),
+            account_required: Self::parse_pubkeys(&config.account_required),
+            account_exclude: Self::parse_pubkeys(&config.account_exclude),
+            signer_filters: Self::parse_pubkeys(&config.signer_filters),
+            writable_filters: Self::parse_pubkeys(&config.writable_filters),
+            instruction_filters: config
+                .instruction_filters
                 .iter()
-                .flat_map(|p| Pubkey::from_str(p).ok().map(|p| p.to_bytes()))
-                .collect(),
-            account_ignores: config
-                .account_ignores
-                .iter()
-                .flat_map(|p| Pubkey::from_str(p).ok().map(|p| p.to_bytes()))
-                .collect(),
-            account_filters: config
-                .account_filters
-                .iter()
-                .flat_map(|p| Pubkey::from_str(p).ok().map(|p| p.to_bytes()))
+                .map(InvocationFilter::new)
                 .collect(),
+            log_include: config.log_include.iter().map(LogFilter::new).collect(),
+            log_exclude: config.log_exclude.iter().map(LogFilter::new).collect(),
+            partition_key: config.partition_key,
             include_vote_transactions: config.include_vote_transactions,
             include_failed_transactions: config.include_failed_transactions,
 
             update_account_topic: config.update_account_topic.clone(),
             slot_status_topic: config.slot_status_topic.clone(),
             transaction_topic: config.transaction_topic.clone(),
+            block_metadata_topic: config.block_metadata_topic.clone(),
+            entry_topic: config.entry_topic.clone(),
 
-            wrap_messages: config.wrap_messages,
+            format: config.format(),
         }
     }
 
@@ -74,6 +212,117 @@ impl Filter {
         }
     }
 
+    /// Returns true if the account data satisfies every data filter.
+    pub fn wants_account_data(&self, data: &[u8]) -> bool {
+        self.account_data_filters
+            .iter()
+            .all(|filter| filter.matches(data))
+    }
+
+    /// Like `wants_account_data`, for an event that may only carry slices of the data.
+    /// A memcmp only matches if its bytes lie within a single published slice.
+    pub fn wants_sliced_account_data(&self, ev: &UpdateAccountEvent) -> bool {
+        if ev.data_slices.is_empty() {
+            return self.wants_account_data(&ev.data);
+        }
+
+        self.account_data_filters.iter().all(|filter| match filter {
+            DataFilter::Memcmp { offset, bytes } => {
+                // An offset this close to usize::MAX lies beyond any account.
+                let Some(end) = offset.checked_add(bytes.len()) else {
+                    return false;
+                };
+                let (offset, end) = (*offset as u64, end as u64);
+                let mut position = 0u64;
+                ev.data_slices.iter().any(|slice| {
+                    let start = position;
+                    position = position.saturating_add(slice.length);
+                    offset >= slice.offset
+                        && slice
+                            .offset
+                            .checked_add(slice.length)
+                            .is_some_and(|slice_end| end <= slice_end)
+                        && usize::try_from(start + (offset - slice.offset))
+                            .ok()
+                            .and_then(|index| ev.data.get(index..))
+                            .is_some_and(|data| data.starts_with(bytes))
+                })
+            }
+            DataFilter::DataSize(size) => ev.data_len == Some(*size),
+            DataFilter::InvalidMemcmp => false,
+        })
+    }
+
+    /// Returns the account data to publish and, if it was sliced, the slices it holds.
+    /// Slices are clipped to the end of the data, like RPC `dataSlice`.
+    pub fn slice_account_data(&self, data: &[u8]) -> (Vec<u8>, Vec<AccountDataSlice>) {
+        if self.data_slices.is_empty() {
+            return (data.to_vec(), Vec::new());
+        }
+
+        let mut sliced = Vec::with_capacity(self.data_slices.iter().map(|s| s.length).sum());
+        let slices = self
+            .data_slices
+            .iter()
+            .map(|slice| {
+                let start = slice.offset.min(data.len());
+                let end = slice.offset.saturating_add(slice.length).min(data.len());
+                sliced.extend_from_slice(&data[start..end]);
+                AccountDataSlice {
+                    offset: start as u64,
+                    length: (end - start) as u64,
+                }
+            })
+            .collect();
+        (sliced, slices)
+    }
+
+    /// Applies `account_include`, `account_required`, `account_exclude`,
+    /// `signer_filters` and `writable_filters` to the accounts of a transaction.
+    pub fn wants_transaction_accounts(&self, accounts: &[TransactionAccount<'_>]) -> bool {
+        let in_set = |set: &HashSet<[u8; 32]>, account: &TransactionAccount| {
+            <&[u8; 32]>::try_from(account.key).is_ok_and(|key| set.contains(key))
+        };
+        let any_in = |set: &HashSet<[u8; 32]>, eligible: fn(&TransactionAccount) -> bool| {
+            set.is_empty()
+                || accounts
+                    .iter()
+                    .any(|account| eligible(account) && in_set(set, account))
+        };
+
+        any_in(&self.account_include, |_| true)
+            && !accounts
+                .iter()
+                .any(|account| in_set(&self.account_exclude, account))
+            && self.account_required.iter().all(|required| {
+                accounts
+                    .iter()
+                    .any(|account| account.key == required.as_slice())
+            })
+            && any_in(&self.signer_filters, |account| account.is_signer)
+            && any_in(&self.writable_filters, |account| account.is_writable)
+    }
+
+    /// Returns true if one of the executed instructions matches an instruction filter.
+    pub fn wants_instructions(&self, instructions: &[InvokedInstruction<'_>]) -> bool {
+        self.instruction_filters.is_empty()
+            || instructions.iter().any(|instruction| {
+                self.instruction_filters
+                    .iter()
+                    .any(|filter| filter.matches(instruction))
+            })
+    }
+
+    /// Returns true if the log messages match an include pattern, when any are
+    /// configured, and no exclude pattern.
+    pub fn wants_logs(&self, logs: &[String]) -> bool {
+        let matches = |filters: &[LogFilter]| {
+            logs.iter()
+                .any(|line| filters.iter().any(|filter| filter.matches(line)))
+        };
+        (self.log_include.is_empty() || matches(&self.log_include)) && !matches(&self.log_exclude)
+    }
+
     pub fn wants_vote_tx(&self) -> bool {
         self.include_vote_transactions
     }
@@ -93,4 +342,566 @@ impl Filter {
     pub fn has_transaction_topic(&self) -> bool {
         !self.transaction_topic.is_empty()
     }
+
+    pub fn has_block_metadata_topic(&self) -> bool {
+        !self.block_metadata_topic.is_empty()
+    }
+
+    pub fn has_entry_topic(&self) -> bool {
+        !self.entry_topic.is_empty()
+    }
+
+    /// Applies the same checks the plugin makes before publishing to an already
+    /// built event that was published under `topic`.
+    pub fn wants_message(&self, topic: &str, message: &EventMessage) -> bool {
+        match message {
+            Account(ev) => {
+                self.update_account_topic == topic
+                    && self.wants_program(&ev.owner)
+                    && self.wants_account(&ev.pubkey)
+                    && self.wants_sliced_account_data(ev)
+            }
+            Slot(_) => self.slot_status_topic == topic,
+            Transaction(ev) => {
+                self.transaction_topic == topic
+                    && (self.wants_vote_tx() || !ev.is_vote)
+                    && (self.wants_failed_tx() || !ev.is_failed())
+                    && ev
+                        .account_keys()
+                        .into_iter()
+                        .any(|key| self.wants_program(key) || self.wants_account(key))
+                    && self.wants_transaction_accounts(&ev.accounts())
+                    && self.wants_instructions(&ev.instructions())
+                    && self.wants_logs(
+                        ev.transaction_status_meta
+                            .as_ref()
+                            .map_or(&[][..], |meta| meta.log_messages.as_slice()),
+                    )
+            }
+            BlockMetadata(_) => self.block_metadata_topic == topic,
+            Entry(_) => self.entry_topic == topic,
+        }
+    }
+
+    /// Describes every setting that differs between this filter and `other`.
+    pub fn diff(&self, other: &Filter) -> Vec<String> {
+        let mut changes = Vec::new();
+        let mut field = |name: &str, old: &dyn Debug, new: &dyn Debug, changed: bool| {
+            if changed {
+                changes.push(format!("{name}: {old:?} -> {new:?}"));
+            }
+        };
+
+        field("sink", &self.sink, &other.sink, self.sink != other.sink);
+        for (name, old, new) in [
+            (
+                "update_account_topic",
+                &self.update_account_topic,
+                &other.update_account_topic,
+            ),
+            (
+                "slot_status_topic",
+                &self.slot_status_topic,
+                &other.slot_status_topic,
+            ),
+            (
+                "transaction_topic",
+                &self.transaction_topic,
+                &other.transaction_topic,
+            ),
+            (
+                "block_metadata_topic",
+                &self.block_metadata_topic,
+                &other.block_metadata_topic,
+            ),
+            ("entry_topic", &self.entry_topic, &other.entry_topic),
+        ] {
+            field(name, old, new, old != new);
+        }
+        for (name, old, new) in [
+            (
+                "publish_all_accounts",
+                self.publish_all_accounts,
+                other.publish_all_accounts,
+            ),
+            (
+                "include_vote_transactions",
+                self.include_vote_transactions,
+                other.include_vote_transactions,
+            ),
+            (
+                "include_failed_transactions",
+                self.include_failed_transactions,
+                other.include_failed_transactions,
+            ),
+        ] {
+            field(name, &old, &new, old != new);
+        }
+
+        field(
+            "instruction_filters",
+            &self.instruction_filters,
+            &other.instruction_filters,
+            self.instruction_filters != other.instruction_filters,
+        );
+        field(
+            "format",
+            &self.format,
+            &other.format,
+            self.format != other.format,
+        );
+        field(
+            "partition_key",
+            &self.partition_key,
+            &other.partition_key,
+            self.partition_key != other.partition_key,
+        );
+        field(
+            "log_include",
+            &self.log_include,
+            &other.log_include,
+            self.log_include != other.log_include,
+        );
+        field(
+            "log_exclude",
+            &self.log_exclude,
+            &other.log_exclude,
+            self.log_exclude != other.log_exclude,
+        );
+        field(
+            "data_slices",
+            &self.data_slices,
+            &other.data_slices,
+            self.data_slices != other.data_slices,
+        );
+        field(
+            "account_data_filters",
+            &self.account_data_filters,
+            &other.account_data_filters,
+            self.account_data_filters != other.account_data_filters,
+        );
+
+        for (name, old, new) in [
+            (
+                "program_ignores",
+                &self.program_ignores,
+                &other.program_ignores,
+            ),
+            (
+                "account_ignores",
+                &self.account_ignores,
+                &other.account_ignores,
+            ),
+            (
+                "program_filters",
+                &self.program_filters,
+                &other.program_filters,
+            ),
+            (
+                "account_filters",
+                &self.account_filters,
+                &other.account_filters,
+            ),
+            (
+                "account_include",
+                &self.account_include,
+                &other.account_include,
+            ),
+            (
+                "account_required",
+                &self.account_required,
+                &other.account_required,
+            ),
+            (
+                "account_exclude",
+                &self.account_exclude,
+                &other.account_exclude,
+            ),
+            (
+                "signer_filters",
+                &self.signer_filters,
+                &other.signer_filters,
+            ),
+            (
+                "writable_filters",
+                &self.writable_filters,
+                &other.writable_filters,
+            ),
+        ] {
+            let added = Self::describe_keys(new.difference(old));
+            let removed = Self::describe_keys(old.difference(new));
+            if !added.is_empty() {
+                changes.push(format!("{name} added {}", added.join(", ")));
+            }
+            if !removed.is_empty() {
+                changes.push(format!("{name} removed {}", removed.join(", ")));
+            }
+        }
+
+        changes
+    }
+
+    /// Invalid keys are skipped; `ConfigFilter::validate` rejects them before a
+    /// filter is built.
+    fn parse_pubkeys(keys: &[String]) -> HashSet<[u8; 32]> {
+        keys.iter()
+            .flat_map(|p| Pubkey::from_str(p).ok().map(|p| p.to_bytes()))
+            .collect()
+    }
+
+    fn describe_keys<'a>(keys: impl Iterator<Item = &'a [u8; 32]>) -> Vec<String> {
+        let mut keys: Vec<_> = keys
+            .map(|key| Pubkey::new_from_array(*key).to_string())
+            .collect();
+        keys.sort_unstable();
+        keys
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(json: &str) -> Filter {
+        let config: ConfigFilter = serde_json::from_str(json).unwrap();
+        Filter::new(&config)
+    }
+
+    fn memcmp(offset: usize, bytes: &[u8]) -> DataFilter {
+        DataFilter::Memcmp {
+            offset,
+            bytes: bytes.to_vec(),
+        }
+    }
+
+    fn sliced_event(data: &[u8], slices: &[(u64, u64)], data_len: u64) -> UpdateAccountEvent {
+        UpdateAccountEvent {
+            data: data.to_vec(),
+            data_slices: slices
+                .iter()
+                .map(|&(offset, length)| AccountDataSlice { offset, length })
+                .collect(),
+            data_len: Some(data_len),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn memcmp_compares_bytes_at_the_offset() {
+        let data = [1, 2, 3, 4];
+        assert!(memcmp(0, &[1, 2]).matches(&data));
+        assert!(memcmp(2, &[3, 4]).matches(&data));
+        assert!(memcmp(4, &[]).matches(&data));
+        assert!(!memcmp(1, &[1]).matches(&data));
+        assert!(!memcmp(3, &[4, 5]).matches(&data));
+        assert!(!memcmp(5, &[]).matches(&data));
+        assert!(!memcmp(usize::MAX, &[1]).matches(&data));
+        assert!(!DataFilter::InvalidMemcmp.matches(&data));
+    }
+
+    #[test]
+    fn data_size_compares_the_length() {
+        assert!(DataFilter::DataSize(3).matches(&[0; 3]));
+        assert!(!DataFilter::DataSize(3).matches(&[0; 4]));
+    }
+
+    #[test]
+    fn account_data_must_match_every_filter() {
+        let filter = filter(
+            r#"{"update_account_topic": "accounts",
+                "account_data_filters": [{"data-size": 4}, {"memcmp": {"offset": 1, "bytes": "AgM=", "encoding": "base64"}}]}"#,
+        );
+        assert!(filter.wants_account_data(&[1, 2, 3, 4]));
+        assert!(!filter.wants_account_data(&[1, 2, 3]));
+        assert!(!filter.wants_account_data(&[1, 2, 4, 4]));
+        assert!(Filter::new(&ConfigFilter::default()).wants_account_data(&[]));
+    }
+
+    #[test]
+    fn undecodable_memcmp_matches_nothing() {
+        let filter = filter(
+            r#"{"update_account_topic": "accounts",
+                "account_data_filters": [{"memcmp": {"offset": 0, "bytes": "0OIl"}}]}"#,
+        );
+        assert_eq!(filter.account_data_filters, [DataFilter::InvalidMemcmp]);
+        assert!(!filter.wants_account_data(&[0; 8]));
+    }
+
+    #[test]
+    fn sliced_memcmp_needs_the_bytes_within_one_slice() {
+        // Bytes 2..4 and 8..10 of the account [0, 1, .., 9].
+        let event = sliced_event(&[2, 3, 8, 9], &[(2, 2), (8, 2)], 10);
+        let wants = |filter: DataFilter| {
+            let mut sliced = Filter::new(&ConfigFilter::default());
+            sliced.account_data_filters = vec![filter];
+            sliced.wants_sliced_account_data(&event)
+        };
+
+        assert!(wants(memcmp(2, &[2, 3])));
+        assert!(wants(memcmp(8, &[8])));
+        assert!(wants(memcmp(9, &[9])));
+        assert!(!wants(memcmp(8, &[9])));
+        // Spans both slices, which are not contiguous in the account.
+        assert!(!wants(memcmp(3, &[3, 8])));
+        // Outside every slice, even if the account would match.
+        assert!(!wants(memcmp(0, &[0])));
+        assert!(!wants(memcmp(usize::MAX, &[0])));
+        assert!(!wants(memcmp(usize::MAX - 1, &[0, 0, 0])));
+        assert!(wants(DataFilter::DataSize(10)));
+        assert!(!wants(DataFilter::DataSize(4)));
+    }
+
+    #[test]
+    fn sliced_memcmp_ignores_slices_beyond_the_data() {
+        let event = sliced_event(&[2], &[(2, 2), (u64::MAX, u64::MAX)], 10);
+        let mut filter = Filter::new(&ConfigFilter::default());
+        filter.account_data_filters = vec![memcmp(3, &[3])];
+        assert!(!filter.wants_sliced_account_data(&event));
+    }
+
+    #[test]
+    fn slices_account_data_and_clips_to_its_end() {
+        let filter = filter(
+            r#"{"update_account_topic": "accounts",
+                "data_slices": [{"offset": 1, "length": 2}, {"offset": 4, "length": 10}, {"offset": 20, "length": 1}]}"#,
+        );
+        let (data, slices) = filter.slice_account_data(&[0, 1, 2, 3, 4, 5]);
+        assert_eq!(data, [1, 2, 4, 5]);
+        assert_eq!(
+            slices,
+            [
+                AccountDataSlice {
+                    offset: 1,
+                    length: 2
+                },
+                AccountDataSlice {
+                    offset: 4,
+                    length: 2
+                },
+                AccountDataSlice {
+                    offset: 6,
+                    length: 0
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn publishes_all_data_without_slices() {
+        let filter = Filter::new(&ConfigFilter::default());
+        let (data, slices) = filter.slice_account_data(&[1, 2, 3]);
+        assert_eq!(data, [1, 2, 3]);
+        assert!(slices.is_empty());
+
+        let event = UpdateAccountEvent {
+            data: vec![1, 2, 3],
+            ..Default::default()
+        };
+        let mut filter = filter;
+        filter.account_data_filters = vec![memcmp(1, &[2, 3])];
+        assert!(filter.wants_sliced_account_data(&event));
+    }
+
+    fn key(n: u8) -> [u8; 32] {
+        [n; 32]
+    }
+
+    fn pubkey(n: u8) -> String {
+        Pubkey::new_from_array(key(n)).to_string()
+    }
+
+    fn account(key: &[u8; 32], is_signer: bool, is_writable: bool) -> TransactionAccount<'_> {
+        TransactionAccount {
+            key: key.as_slice(),
+            is_signer,
+            is_writable,
+        }
+    }
+
+    fn accounts(keys: &[[u8; 32]]) -> Vec<TransactionAccount<'_>> {
+        keys.iter().map(|key| account(key, false, false)).collect()
+    }
+
+    #[test]
+    fn transaction_accounts_match_include_required_and_exclude() {
+        let filter = filter(&format!(
+            r#"{{"transaction_topic": "txs",
+                "account_include": ["{}", "{}"],
+                "account_required": ["{}"],
+                "account_exclude": ["{}"]}}"#,
+            pubkey(1),
+            pubkey(2),
+            pubkey(3),
+            pubkey(4)
+        ));
+
+        assert!(filter.wants_transaction_accounts(&accounts(&[key(1), key(3)])));
+        assert!(filter.wants_transaction_accounts(&accounts(&[key(3), key(2)])));
+        // Missing an included account.
+        assert!(!filter.wants_transaction_accounts(&accounts(&[key(3)])));
+        // Missing the required account.
+        assert!(!filter.wants_transaction_accounts(&accounts(&[key(1), key(2)])));
+        // References an excluded account.
+        assert!(!filter.wants_transaction_accounts(&accounts(&[key(1), key(3), key(4)])));
+        assert!(Filter::new(&ConfigFilter::default()).wants_transaction_accounts(&[]));
+    }
+
+    #[test]
+    fn transaction_accounts_match_signers_and_writable_accounts() {
+        let filter = filter(&format!(
+            r#"{{"transaction_topic": "txs", "signer_filters": ["{}"], "writable_filters": ["{}"]}}"#,
+            pubkey(1),
+            pubkey(2)
+        ));
+        let (one, two) = (key(1), key(2));
+
+        assert!(
+            filter.wants_transaction_accounts(&[
+                account(&one, true, false),
+                account(&two, false, true),
+            ])
+        );
+        // The signer only appears as a non-signer.
+        assert!(
+            !filter.wants_transaction_accounts(&[
+                account(&one, false, true),
+                account(&two, false, true),
+            ])
+        );
+        // The writable account is only read.
+        assert!(
+            !filter.wants_transaction_accounts(&[
+                account(&one, true, true),
+                account(&two, true, false),
+            ])
+        );
+    }
+
+    fn instruction<'a>(
+        program: &'a [u8; 32],
+        data: &'a [u8],
+        inner: bool,
+    ) -> InvokedInstruction<'a> {
+        InvokedInstruction {
+            program: program.as_slice(),
+            data,
+            inner,
+        }
+    }
+
+    #[test]
+    fn instructions_match_program_and_discriminator() {
+        let filter = filter(&format!(
+            r#"{{"transaction_topic": "txs", "instruction_filters": [
+                {{"program": "{}", "discriminators": ["AQI=", "Bw=="], "encoding": "base64"}},
+                {{"program": "{}", "include_inner": false}}]}}"#,
+            pubkey(1),
+            pubkey(2)
+        ));
+        let (one, two, three) = (key(1), key(2), key(3));
+
+        assert!(filter.wants_instructions(&[instruction(&one, &[1, 2, 9], false)]));
+        assert!(filter.wants_instructions(&[instruction(&one, &[7], true)]));
+        assert!(filter.wants_instructions(&[
+            instruction(&three, &[], false),
+            instruction(&two, &[5], false),
+        ]));
+        // Wrong discriminator, or too short for one.
+        assert!(!filter.wants_instructions(&[instruction(&one, &[2, 1], false)]));
+        assert!(!filter.wants_instructions(&[instruction(&one, &[1], false)]));
+        // The second filter leaves out CPI.
+        assert!(!filter.wants_instructions(&[instruction(&two, &[5], true)]));
+        assert!(!filter.wants_instructions(&[instruction(&three, &[1, 2], false)]));
+        assert!(!filter.wants_instructions(&[]));
+        assert!(Filter::new(&ConfigFilter::default()).wants_instructions(&[]));
+    }
+
+    #[test]
+    fn undecodable_instruction_filters_match_nothing() {
+        let filter = filter(&format!(
+            r#"{{"transaction_topic": "txs", "instruction_filters": [
+                {{"program": "not a pubkey"}},
+                {{"program": "{}", "discriminators": ["0OIl"]}}]}}"#,
+            pubkey(1)
+        ));
+        assert!(
+            filter
+                .instruction_filters
+                .iter()
+                .all(|filter| filter.program.is_none())
+        );
+        assert!(!filter.wants_instructions(&[instruction(&key(1), &[], false)]));
+    }
+
+    fn logs(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn log_patterns_match_substrings_and_regexes() {
+        let contains = LogFilter::new(&LogPattern::Contains("Instruction: Swap".to_owned()));
+        assert!(contains.matches("Program log: Instruction: Swap"));
+        assert!(!contains.matches("Program log: Instruction: swap"));
+
+        let invoke = LogFilter::new(&LogPattern::Regex(
+            r"^Program \w+ invoke \[[2-9]\]$".to_owned(),
+        ));
+        assert!(invoke.matches("Program Tokenkeg invoke [2]"));
+        assert!(!invoke.matches("Program Tokenkeg invoke [1]"));
+        assert!(!invoke.matches("Program log: Program Tokenkeg invoke [2]"));
+
+        let invalid = LogFilter::new(&LogPattern::Regex("(".to_owned()));
+        assert_eq!(invalid, LogFilter::InvalidRegex("(".to_owned()));
+        assert!(!invalid.matches("("));
+    }
+
+    #[test]
+    fn logs_match_include_and_exclude_patterns() {
+        let swaps = filter(
+            r#"{"transaction_topic": "txs",
+                "log_include": [{"contains": "Instruction: Swap"}, {"regex": "^Program data: "}],
+                "log_exclude": [{"contains": "failed"}]}"#,
+        );
+
+        assert!(swaps.wants_logs(&logs(&["Program log: Instruction: Swap"])));
+        assert!(swaps.wants_logs(&logs(&["Program log: other", "Program data: AQI="])));
+        assert!(!swaps.wants_logs(&logs(&["Program log: Instruction: Transfer"])));
+        assert!(!swaps.wants_logs(&logs(&[
+            "Program log: Instruction: Swap",
+            "Program X failed: custom program error",
+        ])));
+        assert!(!swaps.wants_logs(&[]));
+
+        let exclude_only =
+            filter(r#"{"transaction_topic": "txs", "log_exclude": [{"contains": "vote"}]}"#);
+        assert!(exclude_only.wants_logs(&[]));
+        assert!(exclude_only.wants_logs(&logs(&["Program log: swap"])));
+        assert!(!exclude_only.wants_logs(&logs(&["Program log: vote"])));
+    }
+
+    #[test]
+    fn diff_describes_changed_settings() {
+        let old = filter(&format!(
+            r#"{{"slot_status_topic": "slots", "account_filters": ["{}", "{}"]}}"#,
+            pubkey(1),
+            pubkey(2)
+        ));
+        assert!(old.diff(&old).is_empty());
+
+        let new = filter(&format!(
+            r#"{{"slot_status_topic": "slots-v2", "account_filters": ["{}", "{}"],
+                "include_vote_transactions": false}}"#,
+            pubkey(2),
+            pubkey(3)
+        ));
+        assert_eq!(
+            old.diff(&new),
+            [
+                r#"slot_status_topic: "slots" -> "slots-v2""#.to_owned(),
+                "include_vote_transactions: true -> false".to_owned(),
+                format!("account_filters added {}", pubkey(3)),
+                format!("account_filters removed {}", pubkey(1)),
+            ]
+        );
+    }
 }
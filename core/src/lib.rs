@@ -1,17 +1,43 @@
 use agave_geyser_plugin_interface::geyser_plugin_interface::GeyserPlugin;
 
 mod config;
-mod event;
+mod context;
+mod dispatcher;
 mod filter;
 mod plugin;
 mod publisher;
+mod reload;
+mod replica;
+mod schema;
+mod sink;
+mod spool;
 
 pub use {
-    config::{Config, ConfigFilter, Producer},
-    event::*,
-    filter::Filter,
+    config::{
+        AccountDataFilter, BytesEncoding, CommitStatus, Config, ConfigFilter, DEFAULT_SINK,
+        DataSlice, FileCompression, FileSinkConfig, FsyncPolicy, HeadersConfig, InstructionFilter,
+        KafkaSinkConfig, LogPattern, MemcmpFilter, MessageFormat, OverflowPolicy, PartitionKey,
+        PipelineConfig, ReloadConfig, SchemaRegistryConfig, SinkConfig, SocketSinkConfig,
+        SpoolConfig, TransactionsConfig,
+    },
+    context::{
+        Delivery, DeliveryCounts, DeliveryStats, DeliveryTag, HeimdallProducerContext,
+        SegmentReplay,
+    },
+    dispatcher::{DispatchError, Dispatcher, DispatcherStats},
+    filter::{DataFilter, Filter, InvocationFilter, LogFilter},
+    heimdall_proto::*,
     plugin::HeimdallPlugin,
     publisher::Publisher,
+    reload::{ConfigWatcher, FilterSet},
+    schema::{ConfluentEncoder, HttpSchemaRegistry, MemorySchemaRegistry, SchemaRegistry},
+    sink::{
+        EventSink, FileSink, HEADER_EVENT, HEADER_FORMAT, HEADER_HOST, HEADER_SCHEMA_VERSION,
+        HEADER_SLOT, HEADER_TIMESTAMP, HEADER_VALIDATOR, HEADER_WRAPPED, KafkaSink, MemorySink,
+        OwnedRecord, Producer, Record, RecordHeaders, SinkResult, SlotTransactions, SocketSink,
+        TransactionalProducer,
+    },
+    spool::{SegmentReader, Spool, SpoolStats, SpooledRecord},
 };
 
 #[unsafe(no_mangle)]
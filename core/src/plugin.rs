@@ -1,26 +1,39 @@
 use {
     crate::{
-        CompiledInstruction, Config, Filter, InnerInstruction, InnerInstructions,
-        LegacyLoadedMessage, LegacyMessage, LoadedAddresses, MessageAddressTableLookup,
-        MessageHeader, Publisher, Reward, SanitizedMessage, SanitizedTransaction, SlotStatus,
-        SlotStatusEvent, TransactionEvent, TransactionStatusMeta, TransactionTokenBalance,
-        UiTokenAmount, UpdateAccountEvent, V0LoadedMessage, V0Message, sanitized_message,
+        AccountMeta, BlockMetadataEvent, CompiledInstruction, Config, DispatchError, Dispatcher,
+        EntryEvent, EventKind, EventSink, FileSink, Filter, HttpSchemaRegistry, InnerInstruction,
+        InnerInstructions, InstructionError, InvokedInstruction, KafkaSink, LegacyLoadedMessage,
+        LegacyMessage, LoadedAddresses, MessageAddressTableLookup, MessageHeader, Publisher,
+        Reward, SanitizedMessage, SanitizedTransaction, SinkConfig, SinkResult, SlotStatus,
+        SlotStatusEvent, SocketSink, TransactionAccount, TransactionError, TransactionEvent,
+        TransactionReturnData, TransactionStatusMeta, TransactionTokenBalance, UiTokenAmount,
+        UpdateAccountEvent, V0LoadedMessage, V0Message, account_meta, instruction_error,
+        message_wrapper::EventMessage::{Account, BlockMetadata, Entry, Slot, Transaction},
+        reload::{ConfigWatcher, FilterSet},
+        replica::{AccountInfo, TransactionInfo},
+        sanitized_message, transaction_error,
     },
     agave_geyser_plugin_interface::geyser_plugin_interface::{
-        GeyserPlugin, GeyserPluginError as PluginError, ReplicaAccountInfoV3,
-        ReplicaAccountInfoVersions, ReplicaTransactionInfoV2, ReplicaTransactionInfoVersions,
+        GeyserPlugin, GeyserPluginError as PluginError, ReplicaAccountInfoVersions,
+        ReplicaBlockInfoVersions, ReplicaEntryInfoVersions, ReplicaTransactionInfoVersions,
         Result as PluginResult, SlotStatus as PluginSlotStatus,
     },
-    log::{debug, error, info, log_enabled},
+    log::{debug, info, log_enabled},
     rdkafka::util::get_rdkafka_version,
     solana_pubkey::Pubkey,
-    std::fmt::{Debug, Formatter},
+    std::{
+        collections::HashMap,
+        fmt::{Debug, Formatter},
+        sync::Arc,
+        time::Duration,
+    },
 };
 
 #[derive(Default)]
 pub struct HeimdallPlugin {
-    publisher: Option<Publisher>,
-    filter: Option<Vec<Filter>>,
+    dispatcher: Option<Dispatcher>,
+    filter: Option<Arc<FilterSet>>,
+    watcher: Option<ConfigWatcher>,
 }
 
 impl Debug for HeimdallPlugin {
@@ -35,7 +48,7 @@ impl GeyserPlugin for HeimdallPlugin {
     }
 
     fn on_load(&mut self, config_file: &str, _: bool) -> PluginResult<()> {
-        if self.publisher.is_some() {
+        if self.dispatcher.is_some() {
             return Err(PluginError::Custom("plugin already loaded".into()));
         }
 
@@ -49,15 +62,36 @@ impl GeyserPlugin for HeimdallPlugin {
         let (version_n, version_s) = get_rdkafka_version();
         info!("Using rdkafka version: {:#08x}, {}", version_n, version_s);
 
-        let producer = config.producer().map_err(|error| {
-            error!("Failed to create kafka producer: {error:?}");
-            PluginError::Custom(Box::new(error))
-        })?;
-        info!("Created Kafka ThreadedProducer successfully");
+        let shutdown_timeout = Duration::from_millis(config.shutdown_timeout_ms);
+        let sinks = config
+            .sinks
+            .iter()
+            .map(|sink_config| {
+                let sink: Arc<dyn EventSink> = match sink_config {
+                    SinkConfig::Kafka(kafka_config) => {
+                        Arc::new(KafkaSink::new(kafka_config, shutdown_timeout)?)
+                    }
+                    SinkConfig::File(file_config) => Arc::new(FileSink::new(file_config)?),
+                    SinkConfig::Socket(socket_config) => Arc::new(SocketSink::new(socket_config)?),
+                };
+                Ok((sink_config.name().to_owned(), sink))
+            })
+            .collect::<SinkResult<HashMap<_, _>>>()
+            .map_err(PluginError::Custom)?;
 
-        let publisher = Publisher::new(producer, &config);
-        self.publisher = Some(publisher);
-        self.filter = Some(config.filters.iter().map(Filter::new).collect());
+        let sink_names = sinks.keys().cloned().collect();
+        self.load_with_sinks(&config, sinks);
+        if config.reload.enabled {
+            let watcher = ConfigWatcher::spawn(
+                config_file.into(),
+                Duration::from_millis(config.reload.poll_interval_ms),
+                Arc::clone(self.filter.as_ref().expect("filters are loaded")),
+                sink_names,
+                config.schema_registry.is_some(),
+            )
+            .map_err(|e| PluginError::Custom(Box::new(e)))?;
+            self.watcher = Some(watcher);
+        }
         info!("Heimdall plugin loaded successfully");
 
         Ok(())
@@ -65,7 +99,8 @@ impl GeyserPlugin for HeimdallPlugin {
 
     fn on_unload(&mut self) {
         info!("Unloading Heimdall plugin");
-        self.publisher = None;
+        self.watcher = None;
+        self.dispatcher = None;
         self.filter = None;
     }
 
@@ -80,15 +115,19 @@ impl GeyserPlugin for HeimdallPlugin {
             return Ok(());
         }
 
-        let info = Self::unwrap_update_account(account);
-        let publisher = self.unwrap_publisher();
-        for filter in filters {
+        let info = AccountInfo::from(account);
+        let dispatcher = self.unwrap_dispatcher();
+        for filter in filters.iter() {
             if !filter.update_account_topic.is_empty() {
-                if !filter.wants_program(info.owner) || !filter.wants_account(info.pubkey) {
-                    Self::log_ignore_account_update(info);
+                if !filter.wants_program(info.owner)
+                    || !filter.wants_account(info.pubkey)
+                    || !filter.wants_account_data(info.data)
+                {
+                    Self::log_ignore_account_update(&info);
                     continue;
                 }
 
+                let (data, data_slices) = filter.slice_account_data(info.data);
                 let event = UpdateAccountEvent {
                     slot,
                     pubkey: info.pubkey.to_vec(),
@@ -96,14 +135,22 @@ impl GeyserPlugin for HeimdallPlugin {
                     owner: info.owner.to_vec(),
                     executable: info.executable,
                     rent_epoch: info.rent_epoch,
-                    data: info.data.to_vec(),
+                    data_len: (!data_slices.is_empty()).then_some(info.data.len() as u64),
+                    data,
+                    data_slices,
                     write_version: info.write_version,
-                    txn_signature: info.txn.map(|v| v.signature().as_ref().to_owned()),
+                    txn_signature: info.txn_signature.map(|v| v.as_ref().to_owned()),
                 };
 
-                publisher
-                    .update_account(event, filter.wrap_messages, &filter.update_account_topic)
-                    .map_err(|e| PluginError::AccountsUpdateError { msg: e.to_string() })?;
+                dispatcher
+                    .publish(
+                        &filter.sink,
+                        Account(event),
+                        filter.format,
+                        filter.partition_key,
+                        &filter.update_account_topic,
+                    )
+                    .map_err(Self::publish_error(EventKind::Account))?;
             }
         }
 
@@ -116,9 +163,9 @@ impl GeyserPlugin for HeimdallPlugin {
         parent: Option<u64>,
         status: &PluginSlotStatus,
     ) -> PluginResult<()> {
-        let publisher = self.unwrap_publisher();
+        let dispatcher = self.unwrap_dispatcher();
         let value = SlotStatus::from(status.clone());
-        for filter in self.unwrap_filters() {
+        for filter in self.unwrap_filters().iter() {
             if !filter.slot_status_topic.is_empty() {
                 let event = SlotStatusEvent {
                     slot,
@@ -126,11 +173,20 @@ impl GeyserPlugin for HeimdallPlugin {
                     status: value.into(),
                 };
 
-                publisher
-                    .update_slot_status(event, filter.wrap_messages, &filter.slot_status_topic)
-                    .map_err(|e| PluginError::AccountsUpdateError { msg: e.to_string() })?;
+                dispatcher
+                    .publish(
+                        &filter.sink,
+                        Slot(event),
+                        filter.format,
+                        filter.partition_key,
+                        &filter.slot_status_topic,
+                    )
+                    .map_err(Self::publish_error(EventKind::Slot))?;
             }
         }
+        dispatcher
+            .slot_status(slot, value)
+            .map_err(Self::publish_error(EventKind::Slot))?;
 
         Ok(())
     }
@@ -140,9 +196,46 @@ impl GeyserPlugin for HeimdallPlugin {
         transaction: ReplicaTransactionInfoVersions,
         slot: u64,
     ) -> PluginResult<()> {
-        let info = Self::unwrap_transaction(transaction);
-        let publisher = self.unwrap_publisher();
-        for filter in self.unwrap_filters() {
+        let info = TransactionInfo::from(transaction);
+        let message = info.transaction.message();
+        let accounts: Vec<_> = message
+            .account_keys()
+            .iter()
+            .enumerate()
+            .map(|(index, key)| TransactionAccount {
+                key: key.as_ref(),
+                is_signer: message.is_signer(index),
+                is_writable: message.is_writable(index),
+            })
+            .collect();
+        let account_keys = message.account_keys();
+        let inner_instructions = info
+            .transaction_status_meta
+            .inner_instructions
+            .iter()
+            .flatten()
+            .flat_map(|inner| inner.instructions.iter())
+            .map(|inner| (&inner.instruction, true));
+        let instructions: Vec<_> = message
+            .instructions()
+            .iter()
+            .map(|ix| (ix, false))
+            .chain(inner_instructions)
+            .filter_map(|(ix, inner)| {
+                Some(InvokedInstruction {
+                    program: account_keys.get(ix.program_id_index as usize)?.as_ref(),
+                    data: &ix.data,
+                    inner,
+                })
+            })
+            .collect();
+        let logs = info
+            .transaction_status_meta
+            .log_messages
+            .as_deref()
+            .unwrap_or_default();
+        let dispatcher = self.unwrap_dispatcher();
+        for filter in self.unwrap_filters().iter() {
             if !filter.transaction_topic.is_empty() {
                 let is_failed = info.transaction_status_meta.status.is_err();
                 if (!filter.wants_vote_tx() && info.is_vote)
@@ -152,24 +245,74 @@ impl GeyserPlugin for HeimdallPlugin {
                     continue;
                 }
 
-                if !info
-                    .transaction
-                    .message()
-                    .account_keys()
-                    .iter()
-                    .any(|pubkey| {
-                        filter.wants_program(pubkey.as_ref())
-                            || filter.wants_account(pubkey.as_ref())
-                    })
+                if !accounts.iter().any(|account| {
+                    filter.wants_program(account.key) || filter.wants_account(account.key)
+                }) || !filter.wants_transaction_accounts(&accounts)
+                    || !filter.wants_instructions(&instructions)
+                    || !filter.wants_logs(logs)
                 {
                     debug!("Ignoring transaction {:?}", info.signature);
                     continue;
                 }
 
-                let event = Self::build_transaction_event(slot, info);
-                publisher
-                    .update_transaction(event, filter.wrap_messages, &filter.transaction_topic)
-                    .map_err(|e| PluginError::TransactionUpdateError { msg: e.to_string() })?;
+                let event = Self::build_transaction_event(slot, &info);
+                dispatcher
+                    .publish(
+                        &filter.sink,
+                        Transaction(event),
+                        filter.format,
+                        filter.partition_key,
+                        &filter.transaction_topic,
+                    )
+                    .map_err(Self::publish_error(EventKind::Transaction))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn notify_block_metadata(&self, blockinfo: ReplicaBlockInfoVersions) -> PluginResult<()> {
+        let filters = self.unwrap_filters();
+        if filters
+            .iter()
+            .all(|filter| !filter.has_block_metadata_topic())
+        {
+            return Ok(());
+        }
+
+        let event = Self::build_block_metadata_event(blockinfo);
+        let dispatcher = self.unwrap_dispatcher();
+        for filter in filters.iter() {
+            if filter.has_block_metadata_topic() {
+                dispatcher
+                    .publish(
+                        &filter.sink,
+                        BlockMetadata(event.clone()),
+                        filter.format,
+                        filter.partition_key,
+                        &filter.block_metadata_topic,
+                    )
+                    .map_err(Self::publish_error(EventKind::BlockMetadata))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn notify_entry(&self, entry: ReplicaEntryInfoVersions) -> PluginResult<()> {
+        let event = Self::build_entry_event(entry);
+        let dispatcher = self.unwrap_dispatcher();
+        for filter in self.unwrap_filters().iter() {
+            if filter.has_entry_topic() {
+                dispatcher
+                    .publish(
+                        &filter.sink,
+                        Entry(event.clone()),
+                        filter.format,
+                        filter.partition_key,
+                        &filter.entry_topic,
+                    )
+                    .map_err(Self::publish_error(EventKind::Entry))?;
             }
         }
 
@@ -189,6 +332,11 @@ impl GeyserPlugin for HeimdallPlugin {
             .iter()
             .any(|filter| !filter.transaction_topic.is_empty())
     }
+
+    fn entry_notifications_enabled(&self) -> bool {
+        let filters = self.unwrap_filters();
+        filters.iter().any(|filter| filter.has_entry_topic())
+    }
 }
 
 impl HeimdallPlugin {
@@ -196,41 +344,38 @@ impl HeimdallPlugin {
         Default::default()
     }
 
-    fn unwrap_publisher(&self) -> &Publisher {
-        self.publisher.as_ref().expect("publisher is unavailable")
+    /// Starts the publish pipeline with already constructed sinks, keyed by the
+    /// names the configured filters route to.
+    pub fn load_with_sinks(&mut self, config: &Config, sinks: HashMap<String, Arc<dyn EventSink>>) {
+        let mut publisher = Publisher::new(sinks);
+        if let Some(registry_config) = &config.schema_registry {
+            publisher =
+                publisher.with_schema_registry(Box::new(HttpSchemaRegistry::new(registry_config)));
+        }
+        self.dispatcher = Some(Dispatcher::new(publisher, &config.pipeline));
+        self.filter = Some(Arc::new(FilterSet::new(
+            config.filters.iter().map(Filter::new).collect(),
+        )));
     }
 
-    fn unwrap_filters(&self) -> &Vec<Filter> {
-        self.filter.as_ref().expect("filter is unavailable")
+    /// Maps a failure to queue an event to the error the validator expects for its kind.
+    fn publish_error(kind: EventKind) -> impl FnOnce(DispatchError) -> PluginError {
+        move |e| match kind {
+            EventKind::Account => PluginError::AccountsUpdateError { msg: e.to_string() },
+            EventKind::Slot => PluginError::SlotStatusUpdateError { msg: e.to_string() },
+            EventKind::Transaction => PluginError::TransactionUpdateError { msg: e.to_string() },
+            EventKind::BlockMetadata | EventKind::Entry => PluginError::Custom(Box::new(e)),
+        }
     }
 
-    fn unwrap_update_account(account: ReplicaAccountInfoVersions) -> &ReplicaAccountInfoV3 {
-        match account {
-            ReplicaAccountInfoVersions::V0_0_1(_info) => {
-                panic!(
-                    "ReplicaAccountInfoVersions::V0_0_1 unsupported, please upgrade your Solana node."
-                );
-            }
-            ReplicaAccountInfoVersions::V0_0_2(_info) => {
-                panic!(
-                    "ReplicaAccountInfoVersions::V0_0_2 unsupported, please upgrade your Solana node."
-                );
-            }
-            ReplicaAccountInfoVersions::V0_0_3(info) => info,
-        }
+    fn unwrap_dispatcher(&self) -> &Dispatcher {
+        self.dispatcher.as_ref().expect("dispatcher is unavailable")
     }
 
-    fn unwrap_transaction(
-        transaction: ReplicaTransactionInfoVersions,
-    ) -> &ReplicaTransactionInfoV2 {
-        match transaction {
-            ReplicaTransactionInfoVersions::V0_0_1(_info) => {
-                panic!(
-                    "ReplicaTransactionInfoVersions::V0_0_1 unsupported, please upgrade your Solana node."
-                );
-            }
-            ReplicaTransactionInfoVersions::V0_0_2(info) => info,
-        }
+    /// Returns the filters in effect right now; a reload does not affect a notification
+    /// that is already being processed.
+    fn unwrap_filters(&self) -> Arc<Vec<Filter>> {
+        self.filter.as_ref().expect("filter is unavailable").load()
     }
 
     fn build_compiled_instruction(
@@ -278,20 +423,299 @@ impl HeimdallPlugin {
         }
     }
 
+    fn build_account_metas(message: &solana_message::SanitizedMessage) -> Vec<AccountMeta> {
+        let num_static = message.static_account_keys().len();
+        message
+            .account_keys()
+            .iter()
+            .enumerate()
+            .map(|(i, key)| {
+                let mut meta = AccountMeta {
+                    pubkey: key.as_ref().into(),
+                    is_signer: message.is_signer(i),
+                    is_writable: message.is_writable(i),
+                    ..Default::default()
+                };
+                if i >= num_static {
+                    meta.set_source(account_meta::Source::LookupTable);
+                }
+                meta
+            })
+            .collect()
+    }
+
+    fn build_loaded_addresses(
+        loaded_addresses: &solana_message::v0::LoadedAddresses,
+    ) -> LoadedAddresses {
+        LoadedAddresses {
+            writable: loaded_addresses
+                .writable
+                .iter()
+                .map(|x| x.as_ref().into())
+                .collect(),
+            readonly: loaded_addresses
+                .readonly
+                .iter()
+                .map(|x| x.as_ref().into())
+                .collect(),
+        }
+    }
+
+    fn build_transaction_error(
+        error: &solana_transaction_error::TransactionError,
+    ) -> TransactionError {
+        use {solana_transaction_error::TransactionError as E, transaction_error::Kind};
+
+        let mut built = TransactionError::default();
+        let kind = match error {
+            E::InstructionError(index, instruction_error) => {
+                built.instruction_index = Some(*index as u32);
+                built.instruction_error = Some(Self::build_instruction_error(instruction_error));
+                Kind::InstructionError
+            }
+            E::DuplicateInstruction(index) => {
+                built.instruction_index = Some(*index as u32);
+                Kind::DuplicateInstruction
+            }
+            E::InsufficientFundsForRent { account_index } => {
+                built.account_index = Some(*account_index as u32);
+                Kind::InsufficientFundsForRent
+            }
+            E::ProgramExecutionTemporarilyRestricted { account_index } => {
+                built.account_index = Some(*account_index as u32);
+                Kind::ProgramExecutionTemporarilyRestricted
+            }
+            E::AccountInUse => Kind::AccountInUse,
+            E::AccountLoadedTwice => Kind::AccountLoadedTwice,
+            E::AccountNotFound => Kind::AccountNotFound,
+            E::ProgramAccountNotFound => Kind::ProgramAccountNotFound,
+            E::InsufficientFundsForFee => Kind::InsufficientFundsForFee,
+            E::InvalidAccountForFee => Kind::InvalidAccountForFee,
+            E::AlreadyProcessed => Kind::AlreadyProcessed,
+            E::BlockhashNotFound => Kind::BlockhashNotFound,
+            E::CallChainTooDeep => Kind::CallChainTooDeep,
+            E::MissingSignatureForFee => Kind::MissingSignatureForFee,
+            E::InvalidAccountIndex => Kind::InvalidAccountIndex,
+            E::SignatureFailure => Kind::SignatureFailure,
+            E::InvalidProgramForExecution => Kind::InvalidProgramForExecution,
+            E::SanitizeFailure => Kind::SanitizeFailure,
+            E::ClusterMaintenance => Kind::ClusterMaintenance,
+            E::AccountBorrowOutstanding => Kind::AccountBorrowOutstanding,
+            E::WouldExceedMaxBlockCostLimit => Kind::WouldExceedMaxBlockCostLimit,
+            E::UnsupportedVersion => Kind::UnsupportedVersion,
+            E::InvalidWritableAccount => Kind::InvalidWritableAccount,
+            E::WouldExceedMaxAccountCostLimit => Kind::WouldExceedMaxAccountCostLimit,
+            E::WouldExceedAccountDataBlockLimit => Kind::WouldExceedAccountDataBlockLimit,
+            E::TooManyAccountLocks => Kind::TooManyAccountLocks,
+            E::AddressLookupTableNotFound => Kind::AddressLookupTableNotFound,
+            E::InvalidAddressLookupTableOwner => Kind::InvalidAddressLookupTableOwner,
+            E::InvalidAddressLookupTableData => Kind::InvalidAddressLookupTableData,
+            E::InvalidAddressLookupTableIndex => Kind::InvalidAddressLookupTableIndex,
+            E::InvalidRentPayingAccount => Kind::InvalidRentPayingAccount,
+            E::WouldExceedMaxVoteCostLimit => Kind::WouldExceedMaxVoteCostLimit,
+            E::WouldExceedAccountDataTotalLimit => Kind::WouldExceedAccountDataTotalLimit,
+            E::MaxLoadedAccountsDataSizeExceeded => Kind::MaxLoadedAccountsDataSizeExceeded,
+            E::InvalidLoadedAccountsDataSizeLimit => Kind::InvalidLoadedAccountsDataSizeLimit,
+            E::ResanitizationNeeded => Kind::ResanitizationNeeded,
+            E::UnbalancedTransaction => Kind::UnbalancedTransaction,
+            E::ProgramCacheHitMaxLimit => Kind::ProgramCacheHitMaxLimit,
+            E::CommitCancelled => Kind::CommitCancelled,
+        };
+        built.set_kind(kind);
+        built
+    }
+
+    fn build_instruction_error(
+        error: &solana_instruction::error::InstructionError,
+    ) -> InstructionError {
+        use {instruction_error::Kind, solana_instruction::error::InstructionError as E};
+
+        let mut built = InstructionError::default();
+        let kind = match error {
+            E::Custom(code) => {
+                built.custom_code = Some(*code);
+                Kind::Custom
+            }
+            E::BorshIoError(message) => {
+                built.borsh_io_error = Some(message.clone());
+                Kind::BorshIoError
+            }
+            E::GenericError => Kind::GenericError,
+            E::InvalidArgument => Kind::InvalidArgument,
+            E::InvalidInstructionData => Kind::InvalidInstructionData,
+            E::InvalidAccountData => Kind::InvalidAccountData,
+            E::AccountDataTooSmall => Kind::AccountDataTooSmall,
+            E::InsufficientFunds => Kind::InsufficientFunds,
+            E::IncorrectProgramId => Kind::IncorrectProgramId,
+            E::MissingRequiredSignature => Kind::MissingRequiredSignature,
+            E::AccountAlreadyInitialized => Kind::AccountAlreadyInitialized,
+            E::UninitializedAccount => Kind::UninitializedAccount,
+            E::UnbalancedInstruction => Kind::UnbalancedInstruction,
+            E::ModifiedProgramId => Kind::ModifiedProgramId,
+            E::ExternalAccountLamportSpend => Kind::ExternalAccountLamportSpend,
+            E::ExternalAccountDataModified => Kind::ExternalAccountDataModified,
+            E::ReadonlyLamportChange => Kind::ReadonlyLamportChange,
+            E::ReadonlyDataModified => Kind::ReadonlyDataModified,
+            E::DuplicateAccountIndex => Kind::DuplicateAccountIndex,
+            E::ExecutableModified => Kind::ExecutableModified,
+            E::RentEpochModified => Kind::RentEpochModified,
+            E::NotEnoughAccountKeys => Kind::NotEnoughAccountKeys,
+            E::AccountDataSizeChanged => Kind::AccountDataSizeChanged,
+            E::AccountNotExecutable => Kind::AccountNotExecutable,
+            E::AccountBorrowFailed => Kind::AccountBorrowFailed,
+            E::AccountBorrowOutstanding => Kind::AccountBorrowOutstanding,
+            E::DuplicateAccountOutOfSync => Kind::DuplicateAccountOutOfSync,
+            E::InvalidError => Kind::InvalidError,
+            E::ExecutableDataModified => Kind::ExecutableDataModified,
+            E::ExecutableLamportChange => Kind::ExecutableLamportChange,
+            E::ExecutableAccountNotRentExempt => Kind::ExecutableAccountNotRentExempt,
+            E::UnsupportedProgramId => Kind::UnsupportedProgramId,
+            E::CallDepth => Kind::CallDepth,
+            E::MissingAccount => Kind::MissingAccount,
+            E::ReentrancyNotAllowed => Kind::ReentrancyNotAllowed,
+            E::MaxSeedLengthExceeded => Kind::MaxSeedLengthExceeded,
+            E::InvalidSeeds => Kind::InvalidSeeds,
+            E::InvalidRealloc => Kind::InvalidRealloc,
+            E::ComputationalBudgetExceeded => Kind::ComputationalBudgetExceeded,
+            E::PrivilegeEscalation => Kind::PrivilegeEscalation,
+            E::ProgramEnvironmentSetupFailure => Kind::ProgramEnvironmentSetupFailure,
+            E::ProgramFailedToComplete => Kind::ProgramFailedToComplete,
+            E::ProgramFailedToCompile => Kind::ProgramFailedToCompile,
+            E::Immutable => Kind::Immutable,
+            E::IncorrectAuthority => Kind::IncorrectAuthority,
+            E::AccountNotRentExempt => Kind::AccountNotRentExempt,
+            E::InvalidAccountOwner => Kind::InvalidAccountOwner,
+            E::ArithmeticOverflow => Kind::ArithmeticOverflow,
+            E::UnsupportedSysvar => Kind::UnsupportedSysvar,
+            E::IllegalOwner => Kind::IllegalOwner,
+            E::MaxAccountsDataAllocationsExceeded => Kind::MaxAccountsDataAllocationsExceeded,
+            E::MaxAccountsExceeded => Kind::MaxAccountsExceeded,
+            E::MaxInstructionTraceLengthExceeded => Kind::MaxInstructionTraceLengthExceeded,
+            E::BuiltinProgramsMustConsumeComputeUnits => {
+                Kind::BuiltinProgramsMustConsumeComputeUnits
+            }
+        };
+        built.set_kind(kind);
+        built
+    }
+
+    fn build_reward(reward: solana_transaction_status::Reward) -> Reward {
+        Reward {
+            pubkey: reward.pubkey,
+            lamports: reward.lamports,
+            post_balance: reward.post_balance,
+            reward_type: reward.reward_type.map(|r| r as i32),
+            commission: match reward.commission {
+                Some(v) => v as u32,
+                None => 0,
+            },
+        }
+    }
+
+    fn build_block_metadata_event(blockinfo: ReplicaBlockInfoVersions) -> BlockMetadataEvent {
+        match blockinfo {
+            ReplicaBlockInfoVersions::V0_0_1(info) => BlockMetadataEvent {
+                slot: info.slot,
+                blockhash: info.blockhash.to_owned(),
+                rewards: info
+                    .rewards
+                    .iter()
+                    .cloned()
+                    .map(Self::build_reward)
+                    .collect(),
+                block_time: info.block_time,
+                block_height: info.block_height,
+                ..Default::default()
+            },
+            ReplicaBlockInfoVersions::V0_0_2(info) => BlockMetadataEvent {
+                slot: info.slot,
+                blockhash: info.blockhash.to_owned(),
+                rewards: info
+                    .rewards
+                    .iter()
+                    .cloned()
+                    .map(Self::build_reward)
+                    .collect(),
+                block_time: info.block_time,
+                block_height: info.block_height,
+                parent_slot: info.parent_slot,
+                parent_blockhash: info.parent_blockhash.to_owned(),
+                executed_transaction_count: info.executed_transaction_count,
+                ..Default::default()
+            },
+            ReplicaBlockInfoVersions::V0_0_3(info) => BlockMetadataEvent {
+                slot: info.slot,
+                blockhash: info.blockhash.to_owned(),
+                rewards: info
+                    .rewards
+                    .iter()
+                    .cloned()
+                    .map(Self::build_reward)
+                    .collect(),
+                block_time: info.block_time,
+                block_height: info.block_height,
+                parent_slot: info.parent_slot,
+                parent_blockhash: info.parent_blockhash.to_owned(),
+                executed_transaction_count: info.executed_transaction_count,
+                entry_count: info.entry_count,
+                num_partitions: None,
+            },
+            ReplicaBlockInfoVersions::V0_0_4(info) => BlockMetadataEvent {
+                slot: info.slot,
+                blockhash: info.blockhash.to_owned(),
+                rewards: info
+                    .rewards
+                    .rewards
+                    .iter()
+                    .cloned()
+                    .map(Self::build_reward)
+                    .collect(),
+                block_time: info.block_time,
+                block_height: info.block_height,
+                parent_slot: info.parent_slot,
+                parent_blockhash: info.parent_blockhash.to_owned(),
+                executed_transaction_count: info.executed_transaction_count,
+                entry_count: info.entry_count,
+                num_partitions: info.rewards.num_partitions,
+            },
+        }
+    }
+
+    fn build_entry_event(entry: ReplicaEntryInfoVersions) -> EntryEvent {
+        match entry {
+            ReplicaEntryInfoVersions::V0_0_1(info) => EntryEvent {
+                slot: info.slot,
+                index: info.index as u64,
+                num_hashes: info.num_hashes,
+                hash: info.hash.to_vec(),
+                executed_transaction_count: info.executed_transaction_count,
+                starting_transaction_index: None,
+            },
+            ReplicaEntryInfoVersions::V0_0_2(info) => EntryEvent {
+                slot: info.slot,
+                index: info.index as u64,
+                num_hashes: info.num_hashes,
+                hash: info.hash.to_vec(),
+                executed_transaction_count: info.executed_transaction_count,
+                starting_transaction_index: Some(info.starting_transaction_index as u64),
+            },
+        }
+    }
+
     fn build_transaction_event(
         slot: u64,
-        ReplicaTransactionInfoV2 {
+        TransactionInfo {
             signature,
             is_vote,
             transaction,
             transaction_status_meta,
             index,
-        }: &ReplicaTransactionInfoV2,
+        }: &TransactionInfo,
     ) -> TransactionEvent {
         TransactionEvent {
             is_vote: *is_vote,
             slot,
-            index: *index as u64,
+            index: index.map(|index| index as u64),
             signature: signature.as_ref().into(),
             transaction_status_meta: Some(TransactionStatusMeta {
                 is_status_err: transaction_status_meta.status.is_err(),
@@ -299,24 +723,17 @@ impl HeimdallPlugin {
                     Err(e) => e.to_string(),
                     Ok(_) => "".to_owned(),
                 },
+                error: transaction_status_meta
+                    .status
+                    .as_ref()
+                    .err()
+                    .map(Self::build_transaction_error),
                 rewards: transaction_status_meta
                     .rewards
                     .clone()
                     .unwrap_or_default()
                     .into_iter()
-                    .map(|x| Reward {
-                        pubkey: x.pubkey,
-                        lamports: x.lamports,
-                        post_balance: x.post_balance,
-                        reward_type: match x.reward_type {
-                            Some(r) => r as i32,
-                            None => 0,
-                        },
-                        commission: match x.commission {
-                            Some(v) => v as u32,
-                            None => 0,
-                        },
-                    })
+                    .map(Self::build_reward)
                     .collect(),
                 fee: transaction_status_meta.fee,
                 log_messages: transaction_status_meta
@@ -353,6 +770,17 @@ impl HeimdallPlugin {
                     .into_iter()
                     .map(Self::build_transaction_token_balance)
                     .collect(),
+                loaded_addresses: Some(Self::build_loaded_addresses(
+                    &transaction_status_meta.loaded_addresses,
+                )),
+                return_data: transaction_status_meta
+                    .return_data
+                    .as_ref()
+                    .map(|return_data| TransactionReturnData {
+                        program_id: return_data.program_id.as_ref().into(),
+                        data: return_data.data.clone(),
+                    }),
+                compute_units_consumed: transaction_status_meta.compute_units_consumed,
             }),
             transaction: Some(SanitizedTransaction {
                 message_hash: transaction.message_hash().to_bytes().into(),
@@ -378,10 +806,7 @@ impl HeimdallPlugin {
                                         .collect(),
                                     recent_block_hash: lv.message.recent_blockhash.as_ref().into(),
                                 }),
-                                is_writable_account_cache: (0..(lv
-                                    .account_keys()
-                                    .len()
-                                    .saturating_sub(1)))
+                                is_writable_account_cache: (0..lv.account_keys().len())
                                     .map(|i: usize| lv.is_writable(i))
                                     .collect(),
                             })
@@ -424,26 +849,10 @@ impl HeimdallPlugin {
                                         })
                                         .collect(),
                                 }),
-                                loaded_adresses: Some(LoadedAddresses {
-                                    writable: v0
-                                        .loaded_addresses
-                                        .writable
-                                        .clone()
-                                        .into_iter()
-                                        .map(|x| x.as_ref().into())
-                                        .collect(),
-                                    readonly: v0
-                                        .loaded_addresses
-                                        .readonly
-                                        .clone()
-                                        .into_iter()
-                                        .map(|x| x.as_ref().into())
-                                        .collect(),
-                                }),
-                                is_writable_account_cache: (0..(v0
-                                    .account_keys()
-                                    .len()
-                                    .saturating_sub(1)))
+                                loaded_adresses: Some(Self::build_loaded_addresses(
+                                    &v0.loaded_addresses,
+                                )),
+                                is_writable_account_cache: (0..v0.account_keys().len())
                                     .map(|i: usize| v0.is_writable(i))
                                     .collect(),
                             })
@@ -456,11 +865,12 @@ impl HeimdallPlugin {
                     .copied()
                     .map(|x| x.as_ref().into())
                     .collect(),
+                accounts: Self::build_account_metas(transaction.message()),
             }),
         }
     }
 
-    fn log_ignore_account_update(info: &ReplicaAccountInfoV3) {
+    fn log_ignore_account_update(info: &AccountInfo) {
         if log_enabled!(::log::Level::Debug) {
             match <&[u8; 32]>::try_from(info.owner) {
                 Ok(key) => debug!(
@@ -472,3 +882,264 @@ impl HeimdallPlugin {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{ConfigFilter, DeliveryTag, EventKind, MemorySink, MessageFormat, OwnedRecord},
+        agave_geyser_plugin_interface::geyser_plugin_interface::{
+            ReplicaAccountInfoV3, ReplicaBlockInfoV4, ReplicaTransactionInfoV2,
+        },
+        prost::Message as _,
+        solana_message::{
+            LegacyMessage as SolanaLegacyMessage, Message as SolanaMessage,
+            SanitizedMessage as SolanaSanitizedMessage,
+            compiled_instruction::CompiledInstruction as SolanaCompiledInstruction,
+        },
+        solana_signature::Signature,
+        solana_transaction::sanitized::SanitizedTransaction as SolanaSanitizedTransaction,
+        solana_transaction_status::{
+            RewardsAndNumPartitions, TransactionStatusMeta as SolanaTransactionStatusMeta,
+        },
+        std::collections::HashSet,
+    };
+
+    const SLOT: u64 = 42;
+
+    /// Loads the plugin with a single filter publishing to a memory sink.
+    fn load(filter: ConfigFilter) -> (HeimdallPlugin, Arc<MemorySink>) {
+        let sink = Arc::new(MemorySink::new());
+        let mut config = Config::default();
+        config.filters.push(filter);
+        let mut plugin = HeimdallPlugin::new();
+        plugin.load_with_sinks(
+            &config,
+            HashMap::from([(
+                config.filters[0].sink.clone(),
+                Arc::clone(&sink) as Arc<dyn EventSink>,
+            )]),
+        );
+        (plugin, sink)
+    }
+
+    /// Stops the publish workers and returns everything they published.
+    fn unload(mut plugin: HeimdallPlugin, sink: &MemorySink) -> Vec<OwnedRecord> {
+        plugin.on_unload();
+        sink.take()
+    }
+
+    fn transaction(signer: Pubkey, program: Pubkey) -> SolanaSanitizedTransaction {
+        let message = SolanaMessage::new_with_compiled_instructions(
+            1,
+            0,
+            1,
+            vec![signer, program],
+            Default::default(),
+            vec![SolanaCompiledInstruction::new_from_raw_parts(
+                1,
+                vec![1, 2, 3],
+                vec![0],
+            )],
+        );
+        SolanaSanitizedTransaction::try_new_from_fields(
+            SolanaSanitizedMessage::Legacy(SolanaLegacyMessage::new(message, &HashSet::new())),
+            Default::default(),
+            false,
+            vec![Signature::from([7; 64])],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn builds_block_metadata_events() {
+        let rewards = RewardsAndNumPartitions {
+            rewards: vec![solana_transaction_status::Reward {
+                pubkey: "validator".to_owned(),
+                lamports: 5000,
+                post_balance: 10_000,
+                reward_type: None,
+                commission: Some(10),
+            }],
+            num_partitions: Some(4),
+        };
+        let info = ReplicaBlockInfoV4 {
+            parent_slot: 9,
+            parent_blockhash: "parent",
+            slot: 10,
+            blockhash: "block",
+            rewards: &rewards,
+            block_time: Some(1_700_000_000),
+            block_height: Some(8),
+            executed_transaction_count: 42,
+            entry_count: 3,
+        };
+
+        let event =
+            HeimdallPlugin::build_block_metadata_event(ReplicaBlockInfoVersions::V0_0_4(&info));
+        assert_eq!(event.slot, 10);
+        assert_eq!(event.blockhash, "block");
+        assert_eq!(event.parent_slot, 9);
+        assert_eq!(event.parent_blockhash, "parent");
+        assert_eq!(event.block_time, Some(1_700_000_000));
+        assert_eq!(event.block_height, Some(8));
+        assert_eq!(event.executed_transaction_count, 42);
+        assert_eq!(event.entry_count, 3);
+        assert_eq!(event.num_partitions, Some(4));
+        assert_eq!(event.rewards.len(), 1);
+        assert_eq!(event.rewards[0].pubkey, "validator");
+        assert_eq!(event.rewards[0].lamports, 5000);
+        assert_eq!(event.rewards[0].commission, 10);
+    }
+
+    #[test]
+    fn publishes_account_updates() {
+        let ignored = Pubkey::new_unique();
+        let (plugin, sink) = load(ConfigFilter {
+            update_account_topic: "accounts".to_owned(),
+            program_ignores: vec![ignored.to_string()],
+            ..Default::default()
+        });
+        let pubkey = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        for owner in [owner, ignored] {
+            let account = ReplicaAccountInfoV3 {
+                pubkey: pubkey.as_ref(),
+                lamports: 1_000,
+                owner: owner.as_ref(),
+                executable: false,
+                rent_epoch: 5,
+                data: &[1, 2, 3],
+                write_version: 9,
+                txn: None,
+            };
+            plugin
+                .update_account(ReplicaAccountInfoVersions::V0_0_3(&account), SLOT, false)
+                .unwrap();
+        }
+
+        let records = unload(plugin, &sink);
+        assert_eq!(records.len(), 1);
+        let record = &records[0];
+        assert_eq!(record.topic, "accounts");
+        assert_eq!(
+            record.tag,
+            DeliveryTag::new(EventKind::Account, MessageFormat::Protobuf)
+        );
+        assert_eq!(record.slot, SLOT);
+        assert_eq!(record.key, pubkey.to_bytes());
+        let event = UpdateAccountEvent::decode(record.payload.as_slice()).unwrap();
+        assert_eq!(event.pubkey, pubkey.to_bytes());
+        assert_eq!(event.owner, owner.to_bytes());
+        assert_eq!(event.lamports, 1_000);
+        assert_eq!(event.rent_epoch, 5);
+        assert_eq!(event.data, [1, 2, 3]);
+        assert_eq!(event.write_version, 9);
+        assert_eq!(event.txn_signature, None);
+    }
+
+    #[test]
+    fn skips_startup_accounts_unless_requested() {
+        let (plugin, sink) = load(ConfigFilter {
+            update_account_topic: "accounts".to_owned(),
+            ..Default::default()
+        });
+        let pubkey = Pubkey::new_unique();
+        let account = ReplicaAccountInfoV3 {
+            pubkey: pubkey.as_ref(),
+            lamports: 1,
+            owner: pubkey.as_ref(),
+            executable: false,
+            rent_epoch: 0,
+            data: &[],
+            write_version: 0,
+            txn: None,
+        };
+        plugin
+            .update_account(ReplicaAccountInfoVersions::V0_0_3(&account), SLOT, true)
+            .unwrap();
+
+        assert!(unload(plugin, &sink).is_empty());
+    }
+
+    #[test]
+    fn publishes_slot_status() {
+        let (plugin, sink) = load(ConfigFilter {
+            slot_status_topic: "slots".to_owned(),
+            format: Some(MessageFormat::ProtobufWrapped),
+            ..Default::default()
+        });
+        plugin
+            .update_slot_status(SLOT, Some(SLOT - 1), &PluginSlotStatus::Confirmed)
+            .unwrap();
+
+        let records = unload(plugin, &sink);
+        assert_eq!(records.len(), 1);
+        let record = &records[0];
+        assert_eq!(record.topic, "slots");
+        assert_eq!(
+            record.tag,
+            DeliveryTag::new(EventKind::Slot, MessageFormat::ProtobufWrapped)
+        );
+        assert_eq!(record.key[0], b'S');
+        assert_eq!(record.key[1..], SLOT.to_le_bytes());
+        let wrapper = crate::MessageWrapper::decode(record.payload.as_slice()).unwrap();
+        let Some(Slot(event)) = wrapper.event_message else {
+            panic!("expected a slot event, got {:?}", wrapper.event_message);
+        };
+        assert_eq!(event.slot, SLOT);
+        assert_eq!(event.parent, SLOT - 1);
+        assert_eq!(event.status(), SlotStatus::Confirmed);
+    }
+
+    #[test]
+    fn publishes_transactions() {
+        let (plugin, sink) = load(ConfigFilter {
+            transaction_topic: "transactions".to_owned(),
+            include_vote_transactions: false,
+            ..Default::default()
+        });
+        let signer = Pubkey::new_unique();
+        let program = Pubkey::new_unique();
+        let transaction = transaction(signer, program);
+        let meta = SolanaTransactionStatusMeta {
+            fee: 5_000,
+            log_messages: Some(vec!["Program log: hello".to_owned()]),
+            compute_units_consumed: Some(1_234),
+            ..Default::default()
+        };
+        for is_vote in [false, true] {
+            let info = ReplicaTransactionInfoV2 {
+                signature: transaction.signature(),
+                is_vote,
+                transaction: &transaction,
+                transaction_status_meta: &meta,
+                index: 3,
+            };
+            plugin
+                .notify_transaction(ReplicaTransactionInfoVersions::V0_0_2(&info), SLOT)
+                .unwrap();
+        }
+
+        let records = unload(plugin, &sink);
+        assert_eq!(records.len(), 1);
+        let record = &records[0];
+        assert_eq!(record.topic, "transactions");
+        assert_eq!(
+            record.tag,
+            DeliveryTag::new(EventKind::Transaction, MessageFormat::Protobuf)
+        );
+        assert_eq!(record.key, transaction.signature().as_ref());
+        let event = TransactionEvent::decode(record.payload.as_slice()).unwrap();
+        assert_eq!(event.signature, transaction.signature().as_ref());
+        assert_eq!(event.slot, SLOT);
+        assert_eq!(event.index, Some(3));
+        assert!(!event.is_vote);
+        assert_eq!(event.account_keys(), [signer.as_ref(), program.as_ref()]);
+        let meta = event.transaction_status_meta.unwrap();
+        assert!(!meta.is_status_err);
+        assert_eq!(meta.fee, 5_000);
+        assert_eq!(meta.log_messages, ["Program log: hello"]);
+        assert_eq!(meta.compute_units_consumed, Some(1_234));
+    }
+}
@@ -1,158 +1,207 @@
 use {
     crate::{
-        Config, MessageWrapper, SlotStatusEvent, TransactionEvent, UpdateAccountEvent,
-        message_wrapper::EventMessage::{self, Account, Slot, Transaction},
+        ConfluentEncoder, DeliveryTag, EventSink, MessageFormat, PartitionKey, Record,
+        SchemaRegistry, SinkResult, SlotStatus,
+        message_wrapper::EventMessage::{self, Account, BlockMetadata, Entry, Slot, Transaction},
     },
-    log::{debug, error, warn},
+    log::debug,
     prost::Message,
-    rdkafka::{
-        error::KafkaError,
-        producer::{BaseRecord, DefaultProducerContext, Producer, ThreadedProducer},
-    },
-    std::time::Duration,
+    std::{collections::HashMap, sync::Arc, time::Duration},
 };
 
+/// Encodes events and routes them to the sink their filter names.
 pub struct Publisher {
-    producer: ThreadedProducer<DefaultProducerContext>,
-    shutdown_timeout: Duration,
+    sinks: HashMap<String, Arc<dyn EventSink>>,
+    confluent: Option<ConfluentEncoder>,
+}
+
+/// An event encoded once, ready to be handed to any sink.
+struct Encoded {
+    tag: DeliveryTag,
+    slot: u64,
+    key: Vec<u8>,
+    partition: Option<i32>,
+    payload: Vec<u8>,
+    message: EventMessage,
+}
+
+impl Encoded {
+    fn record<'a>(&'a self, topic: &'a str) -> Record<'a> {
+        Record {
+            topic,
+            tag: self.tag,
+            slot: self.slot,
+            key: &self.key,
+            partition: self.partition,
+            payload: &self.payload,
+            event: Some(&self.message),
+        }
+    }
 }
 
 impl Publisher {
-    pub fn new(producer: ThreadedProducer<DefaultProducerContext>, config: &Config) -> Self {
+    pub fn new(sinks: HashMap<String, Arc<dyn EventSink>>) -> Self {
         Self {
-            producer,
-            shutdown_timeout: Duration::from_millis(config.shutdown_timeout_ms),
+            sinks,
+            confluent: None,
         }
     }
 
-    pub fn update_account(
+    /// Enables the `confluent-protobuf` format, registering schemas with `registry`.
+    pub fn with_schema_registry(mut self, registry: Box<dyn SchemaRegistry>) -> Self {
+        self.confluent = Some(ConfluentEncoder::new(registry));
+        self
+    }
+
+    pub fn publish(
         &self,
-        ev: UpdateAccountEvent,
-        wrap_messages: bool,
+        sink: &str,
+        message: EventMessage,
+        format: MessageFormat,
+        partition_key: PartitionKey,
         topic: &str,
-    ) -> Result<(), KafkaError> {
-        let temp_key;
-        let (key, buf) = if wrap_messages {
-            temp_key = self.copy_and_prepend(ev.pubkey.as_slice(), 65u8);
-            (&temp_key, Self::encode_with_wrapper(Account(*Box::new(ev))))
-        } else {
-            (&ev.pubkey, ev.encode_to_vec())
-        };
-
-        let record = BaseRecord::<Vec<u8>, _>::to(topic).key(key).payload(&buf);
-
-        match self.producer.send(record) {
-            Ok(_) => {
-                debug!("Successfully sent account update to topic: {}", topic);
-                Ok(())
-            }
-            Err((e, _)) => {
-                error!("Failed to send account update to topic {}: {:?}", topic, e);
-                Err(e)
-            }
-        }
+    ) -> SinkResult<()> {
+        let sink = self.sink(sink)?;
+        let encoded = self.encode(message, format, partition_key, topic)?;
+        sink.send(&encoded.record(topic))
     }
 
-    pub fn update_slot_status(
+    /// Hands an event to the sink's local store without trying to deliver it first.
+    pub fn spill(
         &self,
-        ev: SlotStatusEvent,
-        wrap_messages: bool,
+        sink: &str,
+        message: EventMessage,
+        format: MessageFormat,
+        partition_key: PartitionKey,
         topic: &str,
-    ) -> Result<(), KafkaError> {
-        let temp_key;
-        let (key, buf) = if wrap_messages {
-            temp_key = self.copy_and_prepend(&ev.slot.to_le_bytes(), 83u8);
-            (&temp_key, Self::encode_with_wrapper(Slot(*Box::new(ev))))
-        } else {
-            temp_key = ev.slot.to_le_bytes().to_vec();
-            (&temp_key, ev.encode_to_vec())
-        };
+    ) -> SinkResult<()> {
+        let sink = self.sink(sink)?;
+        let encoded = self.encode(message, format, partition_key, topic)?;
+        sink.spill(&encoded.record(topic))
+    }
 
-        let record = BaseRecord::<Vec<u8>, _>::to(topic).key(key).payload(&buf);
+    pub fn flush(&self, timeout: Duration) -> SinkResult<()> {
+        for (name, sink) in &self.sinks {
+            sink.flush(timeout).inspect_err(|e| {
+                debug!("Failed to flush sink {}: {:?}", name, e);
+            })?;
+        }
+        Ok(())
+    }
 
-        match self.producer.send(record) {
-            Ok(_) => {
-                debug!("Successfully sent slot status update to topic: {}", topic);
-                Ok(())
-            }
-            Err((e, _)) => {
-                error!(
-                    "Failed to send slot status update to topic {}: {:?}",
-                    topic, e
+    /// Reports a slot status to every sink, even if some of them fail.
+    pub fn slot_status(&self, slot: u64, status: SlotStatus) -> SinkResult<()> {
+        let mut result = Ok(());
+        for (name, sink) in &self.sinks {
+            if let Err(e) = sink.slot_status(slot, status) {
+                debug!(
+                    "Sink {} failed to handle status of slot {}: {:?}",
+                    name, slot, e
                 );
-                Err(e)
+                if result.is_ok() {
+                    result = Err(e);
+                }
             }
         }
+        result
+    }
+
+    fn sink(&self, name: &str) -> SinkResult<&Arc<dyn EventSink>> {
+        self.sinks
+            .get(name)
+            .ok_or_else(|| format!("unknown sink {name:?}").into())
+    }
+
+    /// Returns the record key `partition_key` picks for an event.
+    pub fn record_key(message: &EventMessage, partition_key: PartitionKey) -> Vec<u8> {
+        match (partition_key, message) {
+            (PartitionKey::Owner, Account(ev)) => ev.owner.clone(),
+            (PartitionKey::Slot, _) => message.slot().to_le_bytes().to_vec(),
+            (PartitionKey::FeePayer, Transaction(ev)) => ev
+                .account_keys()
+                .first()
+                .map_or_else(|| ev.signature.clone(), |payer| payer.to_vec()),
+            (_, Account(ev)) => ev.pubkey.clone(),
+            (_, Transaction(ev)) => ev.signature.clone(),
+            (_, Slot(_) | BlockMetadata(_) | Entry(_)) => message.slot().to_le_bytes().to_vec(),
+        }
     }
 
-    pub fn update_transaction(
+    /// Returns the delivery tag, slot, record key and payload for an event.
+    fn encode(
         &self,
-        ev: TransactionEvent,
-        wrap_messages: bool,
+        message: EventMessage,
+        format: MessageFormat,
+        partition_key: PartitionKey,
         topic: &str,
-    ) -> Result<(), KafkaError> {
-        let temp_key;
-        let (key, buf) = if wrap_messages {
-            temp_key = self.copy_and_prepend(ev.signature.as_slice(), 84u8);
-            (
-                &temp_key,
-                Self::encode_with_wrapper(Transaction(*Box::new(ev))),
-            )
-        } else {
-            (&ev.signature, ev.encode_to_vec())
+    ) -> SinkResult<Encoded> {
+        let tag = DeliveryTag::new(message.kind(), format);
+        let slot = message.slot();
+        let key = Self::record_key(&message, partition_key);
+        let prefix = match &message {
+            Account(_) => 65u8,
+            Slot(_) => 83u8,
+            Transaction(_) => 84u8,
+            BlockMetadata(_) => 66u8,
+            Entry(_) => 69u8,
+        };
+        let partition = match partition_key {
+            PartitionKey::Partition(partition) => Some(partition),
+            _ => None,
         };
 
-        let record = BaseRecord::<Vec<u8>, _>::to(topic).key(key).payload(&buf);
-
-        match self.producer.send(record) {
-            Ok(_) => {
-                debug!("Successfully sent transaction update to topic: {}", topic);
-                Ok(())
-            }
-            Err((e, _)) => {
-                error!(
-                    "Failed to send transaction update to topic {}: {:?}",
-                    topic, e
-                );
-                Err(e)
+        let (key, payload) = match format {
+            MessageFormat::ProtobufWrapped => (
+                Self::copy_and_prepend(&key, prefix),
+                Self::encode_with_wrapper(&message),
+            ),
+            MessageFormat::Protobuf => (key, Self::encode_event(&message)),
+            MessageFormat::Json => (key, Self::encode_json(&message)?),
+            MessageFormat::ConfluentProtobuf => {
+                let confluent = self
+                    .confluent
+                    .as_ref()
+                    .ok_or("the confluent-protobuf format requires a schema registry")?;
+                let payload = confluent.encode(topic, tag.kind, &Self::encode_event(&message))?;
+                (key, payload)
             }
-        }
-    }
+        };
 
-    fn encode_with_wrapper(message: EventMessage) -> Vec<u8> {
-        MessageWrapper {
-            event_message: Some(message),
-        }
-        .encode_to_vec()
+        Ok(Encoded {
+            tag,
+            slot,
+            key,
+            partition,
+            payload,
+            message,
+        })
     }
 
-    fn copy_and_prepend(&self, data: &[u8], prefix: u8) -> Vec<u8> {
-        let mut temp_key = Vec::with_capacity(data.len() + 1);
-        temp_key.push(prefix);
-        temp_key.extend_from_slice(data);
-        temp_key
+    fn encode_event(message: &EventMessage) -> Vec<u8> {
+        match message {
+            Account(ev) => ev.encode_to_vec(),
+            Slot(ev) => ev.encode_to_vec(),
+            Transaction(ev) => ev.encode_to_vec(),
+            BlockMetadata(ev) => ev.encode_to_vec(),
+            Entry(ev) => ev.encode_to_vec(),
+        }
     }
 
-    pub fn in_flight_count(&self) -> i32 {
-        self.producer.in_flight_count()
+    /// Encodes the event as a `MessageWrapper`, whose only field is the event.
+    pub fn encode_with_wrapper(message: &EventMessage) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(message.encoded_len());
+        message.encode(&mut buf);
+        buf
     }
 
-    pub fn flush(&self, timeout: Duration) -> Result<(), KafkaError> {
-        self.producer.flush(timeout)
+    pub fn encode_json(message: &EventMessage) -> SinkResult<Vec<u8>> {
+        Ok(serde_json::to_vec(message)?)
     }
-}
 
-impl Drop for Publisher {
-    fn drop(&mut self) {
-        debug!("Shutting down Heimdall publisher");
-        match self.producer.flush(self.shutdown_timeout) {
-            Ok(()) => debug!("Publisher shutdown completed successfully"),
-            Err(e) => {
-                warn!(
-                    "Publisher shutdown completed with errors after {:?}: {:?}",
-                    self.shutdown_timeout, e
-                );
-            }
-        }
+    fn copy_and_prepend(data: &[u8], prefix: u8) -> Vec<u8> {
+        let mut temp_key = Vec::with_capacity(data.len() + 1);
+        temp_key.push(prefix);
+        temp_key.extend_from_slice(data);
+        temp_key
     }
 }
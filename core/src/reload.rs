@@ -0,0 +1,241 @@
+use {
+    crate::{Config, Filter, MessageFormat},
+    log::{error, info, warn},
+    std::{
+        collections::HashSet,
+        fs,
+        path::{Path, PathBuf},
+        sync::{
+            Arc, RwLock,
+            atomic::{AtomicBool, Ordering},
+        },
+        thread::{self, JoinHandle},
+        time::{Duration, SystemTime},
+    },
+};
+
+/// The filters the plugin currently applies, replaced as a whole on reload.
+pub struct FilterSet {
+    filters: RwLock<Arc<Vec<Filter>>>,
+}
+
+impl FilterSet {
+    pub fn new(filters: Vec<Filter>) -> Self {
+        Self {
+            filters: RwLock::new(Arc::new(filters)),
+        }
+    }
+
+    pub fn load(&self) -> Arc<Vec<Filter>> {
+        Arc::clone(&self.filters.read().unwrap())
+    }
+
+    /// Replaces the filters, returning the ones that were active before.
+    pub fn swap(&self, filters: Vec<Filter>) -> Arc<Vec<Filter>> {
+        std::mem::replace(&mut *self.filters.write().unwrap(), Arc::new(filters))
+    }
+}
+
+/// Notifications the validator was asked for when the plugin loaded. They cannot
+/// be turned on later, so reloads that would need them only get a warning.
+#[derive(Debug, Clone, Copy)]
+struct Notifications {
+    accounts: bool,
+    transactions: bool,
+    entries: bool,
+}
+
+impl Notifications {
+    fn of(filters: &[Filter]) -> Self {
+        Self {
+            accounts: filters.iter().any(Filter::has_account_topic),
+            transactions: filters.iter().any(Filter::has_transaction_topic),
+            entries: filters.iter().any(Filter::has_entry_topic),
+        }
+    }
+}
+
+/// Polls the config file and swaps in its filters whenever it changes.
+///
+/// Only `filters` are reloaded. Sinks, the pipeline, the schema registry and the
+/// other settings keep the values they were loaded with until the validator restarts,
+/// so filters that need a sink or registry that is not running are rejected.
+pub struct ConfigWatcher {
+    exit: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl ConfigWatcher {
+    pub fn spawn(
+        path: PathBuf,
+        interval: Duration,
+        filters: Arc<FilterSet>,
+        sinks: HashSet<String>,
+        schema_registry: bool,
+    ) -> std::io::Result<Self> {
+        let exit = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let exit = Arc::clone(&exit);
+            let loaded = Notifications::of(&filters.load());
+            thread::Builder::new()
+                .name("heimdallReload".to_owned())
+                .spawn(move || {
+                    Self::run(
+                        &path,
+                        interval,
+                        &filters,
+                        &sinks,
+                        schema_registry,
+                        loaded,
+                        &exit,
+                    )
+                })?
+        };
+        info!("Watching config file for filter changes");
+
+        Ok(Self {
+            exit,
+            handle: Some(handle),
+        })
+    }
+
+    fn run(
+        path: &Path,
+        interval: Duration,
+        filters: &FilterSet,
+        sinks: &HashSet<String>,
+        schema_registry: bool,
+        loaded: Notifications,
+        exit: &AtomicBool,
+    ) {
+        let mut last_modified = Self::modified(path);
+        loop {
+            thread::park_timeout(interval);
+            if exit.load(Ordering::Relaxed) {
+                break;
+            }
+
+            let modified = Self::modified(path);
+            if modified == last_modified {
+                continue;
+            }
+            last_modified = modified;
+
+            if let Err(e) = Self::reload(path, filters, sinks, schema_registry, loaded) {
+                error!("Keeping current filters, failed to reload {path:?}: {e}");
+            }
+        }
+    }
+
+    fn reload(
+        path: &Path,
+        filters: &FilterSet,
+        sinks: &HashSet<String>,
+        schema_registry: bool,
+        loaded: Notifications,
+    ) -> Result<(), String> {
+        let config = Config::read_from(path).map_err(|e| e.to_string())?;
+        for (index, filter) in config.filters.iter().enumerate() {
+            if !sinks.contains(&filter.sink) {
+                return Err(format!(
+                    "filter {index} routes to sink \"{}\" which is not running",
+                    filter.sink
+                ));
+            }
+            if filter.format() == MessageFormat::ConfluentProtobuf && !schema_registry {
+                return Err(format!(
+                    "filter {index} uses the confluent-protobuf format, but the plugin was loaded without a schema_registry"
+                ));
+            }
+        }
+
+        let new_filters: Vec<Filter> = config.filters.iter().map(Filter::new).collect();
+        let wanted = Notifications::of(&new_filters);
+        for (kind, wanted, loaded) in [
+            ("account", wanted.accounts, loaded.accounts),
+            ("transaction", wanted.transactions, loaded.transactions),
+            ("entry", wanted.entries, loaded.entries),
+        ] {
+            if wanted && !loaded {
+                warn!(
+                    "Reloaded filters publish {kind} events, but {kind} notifications were disabled when the plugin loaded; restart the validator to receive them"
+                );
+            }
+        }
+
+        let old_filters = filters.swap(new_filters);
+        let new_filters = filters.load();
+        Self::log_changes(&old_filters, &new_filters);
+        Ok(())
+    }
+
+    fn log_changes(old: &[Filter], new: &[Filter]) {
+        let mut changed = false;
+        for (index, (old, new)) in old.iter().zip(new).enumerate() {
+            for change in old.diff(new) {
+                info!("Reloaded filter {index}: {change}");
+                changed = true;
+            }
+        }
+        for index in new.len()..old.len() {
+            info!("Reloaded filters: removed filter {index}");
+            changed = true;
+        }
+        for index in old.len()..new.len() {
+            info!("Reloaded filters: added filter {index}");
+            changed = true;
+        }
+
+        if !changed {
+            info!("Config file changed, filters are unchanged");
+        }
+    }
+
+    fn modified(path: &Path) -> Option<SystemTime> {
+        fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    }
+}
+
+impl Drop for ConfigWatcher {
+    fn drop(&mut self) {
+        self.exit.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            handle.thread().unpark();
+            if handle.join().is_err() {
+                error!("Config watcher panicked during shutdown");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFLUENT_CONFIG: &str = r#"{
+        "libpath": "",
+        "schema_registry": {"url": "http://localhost:8081"},
+        "sinks": [{"type": "kafka", "name": "kafka", "kafka": {}}],
+        "filters": [{"sink": "kafka", "slot_status_topic": "slots", "format": "confluent-protobuf"}]
+    }"#;
+
+    #[test]
+    fn rejects_confluent_filters_without_running_registry() {
+        let path =
+            std::env::temp_dir().join(format!("heimdall-reload-{}.json", std::process::id()));
+        fs::write(&path, CONFLUENT_CONFIG).unwrap();
+        let filters = FilterSet::new(Vec::new());
+        let sinks = HashSet::from(["kafka".to_owned()]);
+        let loaded = Notifications::of(&[]);
+
+        let error = ConfigWatcher::reload(&path, &filters, &sinks, false, loaded).unwrap_err();
+        assert!(error.contains("confluent-protobuf"), "{error}");
+        assert!(filters.load().is_empty());
+
+        ConfigWatcher::reload(&path, &filters, &sinks, true, loaded).unwrap();
+        assert_eq!(filters.load().len(), 1);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,89 @@
+use {
+    agave_geyser_plugin_interface::geyser_plugin_interface::{
+        ReplicaAccountInfoVersions, ReplicaTransactionInfoVersions,
+    },
+    solana_signature::Signature,
+    solana_transaction::sanitized::SanitizedTransaction,
+    solana_transaction_status::TransactionStatusMeta,
+};
+
+/// An account update with the fields every `ReplicaAccountInfoVersions` provides.
+pub struct AccountInfo<'a> {
+    pub pubkey: &'a [u8],
+    pub lamports: u64,
+    pub owner: &'a [u8],
+    pub executable: bool,
+    pub rent_epoch: u64,
+    pub data: &'a [u8],
+    pub write_version: u64,
+    /// Not reported before `V0_0_2`.
+    pub txn_signature: Option<&'a Signature>,
+}
+
+impl<'a> From<ReplicaAccountInfoVersions<'a>> for AccountInfo<'a> {
+    fn from(account: ReplicaAccountInfoVersions<'a>) -> Self {
+        match account {
+            ReplicaAccountInfoVersions::V0_0_1(info) => Self {
+                pubkey: info.pubkey,
+                lamports: info.lamports,
+                owner: info.owner,
+                executable: info.executable,
+                rent_epoch: info.rent_epoch,
+                data: info.data,
+                write_version: info.write_version,
+                txn_signature: None,
+            },
+            ReplicaAccountInfoVersions::V0_0_2(info) => Self {
+                pubkey: info.pubkey,
+                lamports: info.lamports,
+                owner: info.owner,
+                executable: info.executable,
+                rent_epoch: info.rent_epoch,
+                data: info.data,
+                write_version: info.write_version,
+                txn_signature: info.txn_signature,
+            },
+            ReplicaAccountInfoVersions::V0_0_3(info) => Self {
+                pubkey: info.pubkey,
+                lamports: info.lamports,
+                owner: info.owner,
+                executable: info.executable,
+                rent_epoch: info.rent_epoch,
+                data: info.data,
+                write_version: info.write_version,
+                txn_signature: info.txn.map(|txn| txn.signature()),
+            },
+        }
+    }
+}
+
+/// A transaction notification with the fields every `ReplicaTransactionInfoVersions` provides.
+pub struct TransactionInfo<'a> {
+    pub signature: &'a Signature,
+    pub is_vote: bool,
+    pub transaction: &'a SanitizedTransaction,
+    pub transaction_status_meta: &'a TransactionStatusMeta,
+    /// Position of the transaction in its block, not reported before `V0_0_2`.
+    pub index: Option<usize>,
+}
+
+impl<'a> From<ReplicaTransactionInfoVersions<'a>> for TransactionInfo<'a> {
+    fn from(transaction: ReplicaTransactionInfoVersions<'a>) -> Self {
+        match transaction {
+            ReplicaTransactionInfoVersions::V0_0_1(info) => Self {
+                signature: info.signature,
+                is_vote: info.is_vote,
+                transaction: info.transaction,
+                transaction_status_meta: info.transaction_status_meta,
+                index: None,
+            },
+            ReplicaTransactionInfoVersions::V0_0_2(info) => Self {
+                signature: info.signature,
+                is_vote: info.is_vote,
+                transaction: info.transaction,
+                transaction_status_meta: info.transaction_status_meta,
+                index: Some(info.index),
+            },
+        }
+    }
+}
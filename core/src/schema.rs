@@ -0,0 +1,351 @@
+use {
+    crate::{EventKind, PROTO_SCHEMA, SchemaRegistryConfig, SinkResult},
+    base64::{Engine, prelude::BASE64_STANDARD},
+    log::{info, warn},
+    serde::Deserialize,
+    std::{
+        collections::HashMap,
+        sync::{LazyLock, Mutex, RwLock},
+        time::{Duration, Instant},
+    },
+};
+
+/// Time a topic waits before its schema is registered again after a failure,
+/// doubled with every failure in a row up to `MAX_REGISTRY_BACKOFF`.
+const MIN_REGISTRY_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_REGISTRY_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Top-level messages of `PROTO_SCHEMA` in declaration order, which Confluent's
+/// wire format refers to by position.
+static PROTO_MESSAGES: LazyLock<Vec<&'static str>> = LazyLock::new(|| {
+    PROTO_SCHEMA
+        .lines()
+        .filter_map(|line| line.strip_prefix("message "))
+        .filter_map(|line| line.split_whitespace().next())
+        .collect()
+});
+
+/// Registers schemas and returns their ids.
+pub trait SchemaRegistry: Send + Sync {
+    /// Registers `schema` under `subject`, or looks it up if it already exists.
+    fn register(&self, subject: &str, schema: &str) -> SinkResult<u32>;
+}
+
+/// A Confluent compatible schema registry reached over HTTP.
+pub struct HttpSchemaRegistry {
+    url: String,
+    authorization: Option<String>,
+    agent: ureq::Agent,
+}
+
+#[derive(Deserialize)]
+struct RegisterResponse {
+    id: u32,
+}
+
+impl HttpSchemaRegistry {
+    pub fn new(config: &SchemaRegistryConfig) -> Self {
+        let authorization = config.username.as_ref().map(|username| {
+            let credentials = format!(
+                "{username}:{}",
+                config.password.as_deref().unwrap_or_default()
+            );
+            format!("Basic {}", BASE64_STANDARD.encode(credentials))
+        });
+
+        Self {
+            url: config.url.trim_end_matches('/').to_owned(),
+            authorization,
+            agent: ureq::AgentBuilder::new()
+                .timeout(Duration::from_millis(config.timeout_ms))
+                .build(),
+        }
+    }
+}
+
+impl SchemaRegistry for HttpSchemaRegistry {
+    fn register(&self, subject: &str, schema: &str) -> SinkResult<u32> {
+        let body = serde_json::json!({
+            "schemaType": "PROTOBUF",
+            "schema": schema,
+        });
+        let mut request = self
+            .agent
+            .post(&format!("{}/subjects/{subject}/versions", self.url))
+            .set("Content-Type", "application/vnd.schemaregistry.v1+json");
+        if let Some(authorization) = &self.authorization {
+            request = request.set("Authorization", authorization);
+        }
+
+        let response: RegisterResponse = request.send_json(body)?.into_json()?;
+        Ok(response.id)
+    }
+}
+
+/// Keeps schemas in memory, so tests can run without a registry.
+#[derive(Debug, Default)]
+pub struct MemorySchemaRegistry {
+    schemas: Mutex<Vec<(String, String)>>,
+}
+
+impl MemorySchemaRegistry {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Returns the subject and schema registered under `id`.
+    pub fn get(&self, id: u32) -> Option<(String, String)> {
+        self.schemas
+            .lock()
+            .unwrap()
+            .get(id.checked_sub(1)? as usize)
+            .cloned()
+    }
+}
+
+impl SchemaRegistry for MemorySchemaRegistry {
+    fn register(&self, subject: &str, schema: &str) -> SinkResult<u32> {
+        let mut schemas = self.schemas.lock().unwrap();
+        let index = match schemas.iter().position(|(registered_subject, registered)| {
+            registered_subject == subject && registered == schema
+        }) {
+            Some(index) => index,
+            None => {
+                schemas.push((subject.to_owned(), schema.to_owned()));
+                schemas.len() - 1
+            }
+        };
+        Ok(index as u32 + 1)
+    }
+}
+
+/// Frames protobuf events in Confluent's wire format, registering the schema of
+/// each topic the first time it is used.
+///
+/// While a topic's registration keeps failing, its events don't wait on the
+/// registry and registration is retried with a backoff. In the meantime they are
+/// framed with the last schema id registered for any topic, which Confluent's
+/// registry shares between subjects of the same schema, and only fail if no
+/// schema was registered yet.
+pub struct ConfluentEncoder {
+    registry: Box<dyn SchemaRegistry>,
+    ids: RwLock<HashMap<String, u32>>,
+    last_id: RwLock<Option<u32>>,
+    failures: Mutex<HashMap<String, RegistryFailure>>,
+}
+
+struct RegistryFailure {
+    retry_at: Instant,
+    backoff: Duration,
+}
+
+impl ConfluentEncoder {
+    pub fn new(registry: Box<dyn SchemaRegistry>) -> Self {
+        Self {
+            registry,
+            ids: RwLock::new(HashMap::new()),
+            last_id: RwLock::new(None),
+            failures: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Prefixes `payload`, an encoded event of `kind`, with the magic byte, the
+    /// schema id of `topic` and the index of the event's message.
+    pub fn encode(&self, topic: &str, kind: EventKind, payload: &[u8]) -> SinkResult<Vec<u8>> {
+        let id = self.schema_id(topic)?;
+        let index = Self::message_index(kind)?;
+
+        let mut buf = Vec::with_capacity(payload.len() + 8);
+        buf.push(0);
+        buf.extend_from_slice(&id.to_be_bytes());
+        if index == 0 {
+            // Shorthand for the first message of the schema.
+            buf.push(0);
+        } else {
+            // One index, zigzag encoded like the count before it.
+            prost::encoding::encode_varint(2, &mut buf);
+            prost::encoding::encode_varint(index << 1, &mut buf);
+        }
+        buf.extend_from_slice(payload);
+        Ok(buf)
+    }
+
+    fn schema_id(&self, topic: &str) -> SinkResult<u32> {
+        if let Some(id) = self.ids.read().unwrap().get(topic) {
+            return Ok(*id);
+        }
+
+        if let Some(failure) = self.failures.lock().unwrap().get(topic)
+            && let Some(wait) = failure.retry_at.checked_duration_since(Instant::now())
+        {
+            return self.last_id.read().unwrap().ok_or_else(|| {
+                format!(
+                    "schema of topic {topic} is not registered, retrying in {}ms",
+                    wait.as_millis()
+                )
+                .into()
+            });
+        }
+
+        let subject = format!("{topic}-value");
+        match self.registry.register(&subject, PROTO_SCHEMA) {
+            Ok(id) => {
+                info!("Registered schema {id} for subject {subject}");
+                self.failures.lock().unwrap().remove(topic);
+                self.ids.write().unwrap().insert(topic.to_owned(), id);
+                *self.last_id.write().unwrap() = Some(id);
+                Ok(id)
+            }
+            Err(e) => {
+                let mut failures = self.failures.lock().unwrap();
+                let backoff = failures.get(topic).map_or(MIN_REGISTRY_BACKOFF, |failure| {
+                    (failure.backoff * 2).min(MAX_REGISTRY_BACKOFF)
+                });
+                warn!(
+                    "Failed to register schema for subject {subject}, retrying in {}ms: {e:?}",
+                    backoff.as_millis()
+                );
+                failures.insert(
+                    topic.to_owned(),
+                    RegistryFailure {
+                        retry_at: Instant::now() + backoff,
+                        backoff,
+                    },
+                );
+                self.last_id.read().unwrap().ok_or(e)
+            }
+        }
+    }
+
+    fn message_index(kind: EventKind) -> SinkResult<u64> {
+        let name = match kind {
+            EventKind::Account => "UpdateAccountEvent",
+            EventKind::Slot => "SlotStatusEvent",
+            EventKind::Transaction => "TransactionEvent",
+            EventKind::BlockMetadata => "BlockMetadataEvent",
+            EventKind::Entry => "EntryEvent",
+        };
+        PROTO_MESSAGES
+            .iter()
+            .position(|message| *message == name)
+            .map(|index| index as u64)
+            .ok_or_else(|| format!("message {name} is missing from the schema").into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{SlotStatus, SlotStatusEvent, UpdateAccountEvent, strip_confluent_framing},
+        prost::Message,
+        std::sync::{
+            Arc,
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+        },
+    };
+
+    /// Fails until `available` is set, counting every attempt.
+    #[derive(Default)]
+    struct FlakyRegistry {
+        available: AtomicBool,
+        attempts: AtomicUsize,
+    }
+
+    impl SchemaRegistry for Arc<FlakyRegistry> {
+        fn register(&self, _subject: &str, _schema: &str) -> SinkResult<u32> {
+            self.attempts.fetch_add(1, Ordering::Relaxed);
+            if self.available.load(Ordering::Relaxed) {
+                Ok(3)
+            } else {
+                Err("registry unavailable".into())
+            }
+        }
+    }
+
+    #[test]
+    fn framed_events_decode_after_stripping() {
+        let encoder = ConfluentEncoder::new(Box::new(MemorySchemaRegistry::new()));
+        let account = UpdateAccountEvent {
+            slot: 7,
+            pubkey: vec![1; 32],
+            lamports: 100,
+            data: vec![4, 5, 6],
+            ..Default::default()
+        };
+        let slot = SlotStatusEvent {
+            slot: 8,
+            parent: 7,
+            status: SlotStatus::Rooted.into(),
+        };
+
+        let framed = encoder
+            .encode("accounts", EventKind::Account, &account.encode_to_vec())
+            .unwrap();
+        assert_eq!(framed[..5], [0, 0, 0, 0, 1]);
+        let payload = strip_confluent_framing(&framed).unwrap();
+        assert_eq!(UpdateAccountEvent::decode(payload).unwrap(), account);
+
+        let framed = encoder
+            .encode("slots", EventKind::Slot, &slot.encode_to_vec())
+            .unwrap();
+        assert_eq!(framed[..5], [0, 0, 0, 0, 2]);
+        let payload = strip_confluent_framing(&framed).unwrap();
+        assert_eq!(SlotStatusEvent::decode(payload).unwrap(), slot);
+    }
+
+    #[test]
+    fn backs_off_after_registry_failures() {
+        let registry = Arc::new(FlakyRegistry::default());
+        let encoder = ConfluentEncoder::new(Box::new(Arc::clone(&registry)));
+
+        assert!(encoder.encode("accounts", EventKind::Account, &[]).is_err());
+        assert!(encoder.encode("accounts", EventKind::Account, &[]).is_err());
+        assert_eq!(registry.attempts.load(Ordering::Relaxed), 1);
+
+        // Other topics are not held back.
+        assert!(encoder.encode("slots", EventKind::Slot, &[]).is_err());
+        assert_eq!(registry.attempts.load(Ordering::Relaxed), 2);
+
+        registry.available.store(true, Ordering::Relaxed);
+        encoder
+            .failures
+            .lock()
+            .unwrap()
+            .get_mut("accounts")
+            .unwrap()
+            .retry_at = Instant::now();
+        let framed = encoder.encode("accounts", EventKind::Account, &[]).unwrap();
+        assert_eq!(framed[..5], [0, 0, 0, 0, 3]);
+        assert!(encoder.failures.lock().unwrap().get("accounts").is_none());
+        encoder.encode("accounts", EventKind::Account, &[]).unwrap();
+        assert_eq!(registry.attempts.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn falls_back_to_the_last_registered_schema() {
+        let registry = Arc::new(FlakyRegistry::default());
+        let encoder = ConfluentEncoder::new(Box::new(Arc::clone(&registry)));
+
+        registry.available.store(true, Ordering::Relaxed);
+        encoder.encode("slots", EventKind::Slot, &[]).unwrap();
+        registry.available.store(false, Ordering::Relaxed);
+
+        // Both the failed registration and the events during its backoff use the
+        // schema registered for the other topic.
+        for _ in 0..2 {
+            let framed = encoder.encode("accounts", EventKind::Account, &[]).unwrap();
+            assert_eq!(framed[..5], [0, 0, 0, 0, 3]);
+        }
+        assert_eq!(registry.attempts.load(Ordering::Relaxed), 2);
+        assert!(encoder.ids.read().unwrap().get("accounts").is_none());
+        assert!(encoder.failures.lock().unwrap().get("accounts").is_some());
+    }
+
+    #[test]
+    fn rejects_payloads_without_framing() {
+        assert!(strip_confluent_framing(&[]).is_none());
+        assert!(strip_confluent_framing(&[1, 0, 0, 0, 1, 0]).is_none());
+        assert!(strip_confluent_framing(&[0, 0, 0]).is_none());
+    }
+}
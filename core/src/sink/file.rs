@@ -0,0 +1,420 @@
+use {
+    super::{EventSink, Record, SinkResult},
+    crate::{FileCompression, FileSinkConfig, FsyncPolicy},
+    log::{debug, info, warn},
+    std::{
+        collections::HashMap,
+        fs::{self, File, OpenOptions},
+        io::{self, BufWriter, ErrorKind, Write},
+        path::{Path, PathBuf},
+        sync::Mutex,
+        time::Duration,
+    },
+    zstd::stream::write::Encoder,
+};
+
+const PARTIAL_EXTENSION: &str = "partial";
+
+/// Writes the `MessageWrapper` stream of each topic to rotating local files.
+///
+/// Every record is written as a varint length followed by the encoded wrapper, the
+/// same framing as `prost::Message::encode_length_delimited`. Files live under
+/// `<path>/<topic>/` and are named after the first slot they contain. A file keeps
+/// a `.partial` suffix until it is closed, so readers only pick up complete files.
+pub struct FileSink {
+    name: String,
+    dir: PathBuf,
+    max_file_bytes: u64,
+    slots_per_file: Option<u64>,
+    compression: FileCompression,
+    compression_level: i32,
+    fsync: FsyncPolicy,
+    files: Mutex<HashMap<String, OpenFile>>,
+}
+
+enum Output {
+    Plain(BufWriter<File>),
+    Zstd(Encoder<'static, BufWriter<File>>),
+}
+
+struct OpenFile {
+    path: PathBuf,
+    output: Output,
+    bytes: u64,
+    range: Option<u64>,
+}
+
+impl FileSink {
+    pub fn new(config: &FileSinkConfig) -> io::Result<Self> {
+        fs::create_dir_all(&config.path)?;
+        info!(
+            "Writing events for sink {} to {:?}",
+            config.name, config.path
+        );
+
+        Ok(Self {
+            name: config.name.clone(),
+            dir: config.path.clone(),
+            max_file_bytes: config.max_file_bytes,
+            slots_per_file: config.slots_per_file,
+            compression: config.compression,
+            compression_level: config.compression_level,
+            fsync: config.fsync,
+            files: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn open_file(&self, topic: &str, slot: u64) -> io::Result<OpenFile> {
+        let dir = self.dir.join(topic);
+        fs::create_dir_all(&dir)?;
+
+        let extension = match self.compression {
+            FileCompression::None => "pb",
+            FileCompression::Zstd => "pb.zst",
+        };
+        // Several files can start at the same slot, so the sequence keeps names unique.
+        let mut seq = 0u32;
+        let (path, file) = loop {
+            let path = dir.join(format!("{slot:012}-{seq:04}.{extension}"));
+            let partial = Self::partial_path(&path);
+            if path.exists() {
+                seq += 1;
+                continue;
+            }
+            match OpenOptions::new()
+                .create_new(true)
+                .write(true)
+                .open(&partial)
+            {
+                Ok(file) => break (path, file),
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => seq += 1,
+                Err(e) => return Err(e),
+            }
+        };
+
+        let writer = BufWriter::new(file);
+        let output = match self.compression {
+            FileCompression::None => Output::Plain(writer),
+            FileCompression::Zstd => Output::Zstd(Encoder::new(writer, self.compression_level)?),
+        };
+        debug!("Opened {:?} for sink {}", path, self.name);
+
+        Ok(OpenFile {
+            path,
+            output,
+            bytes: 0,
+            range: self.range_of(slot),
+        })
+    }
+
+    fn close_file(&self, file: OpenFile) -> io::Result<()> {
+        let writer = match file.output {
+            Output::Plain(writer) => writer,
+            Output::Zstd(encoder) => encoder.finish()?,
+        };
+        let file_handle = writer.into_inner().map_err(|e| e.into_error())?;
+        if self.fsync != FsyncPolicy::Never {
+            file_handle.sync_all()?;
+        }
+        fs::rename(Self::partial_path(&file.path), &file.path)?;
+        debug!(
+            "Closed {:?} for sink {} ({} bytes)",
+            file.path, self.name, file.bytes
+        );
+        Ok(())
+    }
+
+    fn range_of(&self, slot: u64) -> Option<u64> {
+        self.slots_per_file.map(|slots| slot / slots)
+    }
+
+    /// Whether a record has to go to a new file. Slot ranges only ever move forward,
+    /// so late updates for an older slot stay in the current file.
+    fn needs_rotation(&self, file: &OpenFile, slot: u64, len: u64) -> bool {
+        (file.bytes > 0 && file.bytes + len > self.max_file_bytes)
+            || self.range_of(slot) > file.range
+    }
+
+    fn partial_path(path: &Path) -> PathBuf {
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".");
+        partial.push(PARTIAL_EXTENSION);
+        partial.into()
+    }
+}
+
+impl EventSink for FileSink {
+    fn send(&self, record: &Record<'_>) -> SinkResult<()> {
+        if !record.tag.is_wrapped() {
+            return Err("file sinks only accept the protobuf-wrapped format".into());
+        }
+
+        let mut frame = Vec::with_capacity(prost::length_delimiter_len(record.payload.len()));
+        prost::encode_length_delimiter(record.payload.len(), &mut frame)?;
+        let len = (frame.len() + record.payload.len()) as u64;
+
+        let mut files = self.files.lock().unwrap();
+        if let Some(file) = files.get(record.topic)
+            && self.needs_rotation(file, record.slot, len)
+        {
+            let file = files.remove(record.topic).expect("open file");
+            self.close_file(file)?;
+        }
+
+        if !files.contains_key(record.topic) {
+            let file = self.open_file(record.topic, record.slot)?;
+            files.insert(record.topic.to_owned(), file);
+        }
+
+        let file = files.get_mut(record.topic).expect("open file");
+        file.output.write_all(&frame)?;
+        file.output.write_all(record.payload)?;
+        file.bytes += len;
+        if self.fsync == FsyncPolicy::Always {
+            file.output.flush()?;
+            file.output.get_ref().sync_data()?;
+        }
+        Ok(())
+    }
+
+    /// Files are already local, so spilled records are simply written out.
+    fn spill(&self, record: &Record<'_>) -> SinkResult<()> {
+        self.send(record)
+    }
+
+    fn flush(&self, _timeout: Duration) -> SinkResult<()> {
+        for file in self.files.lock().unwrap().values_mut() {
+            file.output.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for FileSink {
+    fn drop(&mut self) {
+        debug!("Shutting down file sink {}", self.name);
+        let files = std::mem::take(self.files.get_mut().unwrap());
+        for (topic, file) in files {
+            if let Err(e) = self.close_file(file) {
+                warn!(
+                    "Failed to close file for topic {} on sink {}: {:?}",
+                    topic, self.name, e
+                );
+            }
+        }
+    }
+}
+
+impl Output {
+    fn get_ref(&self) -> &File {
+        match self {
+            Output::Plain(writer) => writer.get_ref(),
+            Output::Zstd(encoder) => encoder.get_ref().get_ref(),
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::Plain(writer) => writer.write(buf),
+            Output::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Plain(writer) => writer.flush(),
+            Output::Zstd(encoder) => {
+                encoder.flush()?;
+                encoder.get_mut().flush()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{DeliveryTag, EventKind, MessageFormat},
+    };
+
+    /// A sink directory that is removed when the test ends.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path =
+                std::env::temp_dir().join(format!("heimdall-files-{name}-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            Self(path)
+        }
+
+        fn config(&self) -> FileSinkConfig {
+            FileSinkConfig {
+                name: "files".to_owned(),
+                path: self.0.clone(),
+                max_file_bytes: 1024,
+                slots_per_file: None,
+                compression: FileCompression::None,
+                compression_level: 3,
+                fsync: FsyncPolicy::Never,
+            }
+        }
+
+        /// File names of a topic, sorted.
+        fn files(&self, topic: &str) -> Vec<String> {
+            let mut names: Vec<_> = fs::read_dir(self.0.join(topic))
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+                .collect();
+            names.sort();
+            names
+        }
+
+        fn read(&self, topic: &str, name: &str) -> Vec<u8> {
+            fs::read(self.0.join(topic).join(name)).unwrap()
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn record(slot: u64, payload: &[u8]) -> Record<'_> {
+        Record {
+            topic: "slots",
+            tag: DeliveryTag::new(EventKind::Slot, MessageFormat::ProtobufWrapped),
+            slot,
+            key: b"key",
+            partition: None,
+            payload,
+            event: None,
+        }
+    }
+
+    fn frames(mut data: &[u8]) -> Vec<Vec<u8>> {
+        let mut frames = Vec::new();
+        while !data.is_empty() {
+            let len = prost::decode_length_delimiter(&mut data).unwrap();
+            let (frame, rest) = data.split_at(len);
+            frames.push(frame.to_vec());
+            data = rest;
+        }
+        frames
+    }
+
+    #[test]
+    fn rotates_files_by_size() {
+        let dir = TempDir::new("size");
+        let sink = FileSink::new(&FileSinkConfig {
+            max_file_bytes: 10,
+            ..dir.config()
+        })
+        .unwrap();
+        // Each frame is a length byte and 6 payload bytes, so only one fits a file.
+        for payload in 1..=3u8 {
+            sink.send(&record(5, &[payload; 6])).unwrap();
+        }
+        drop(sink);
+
+        let files = dir.files("slots");
+        assert_eq!(
+            files,
+            [
+                "000000000005-0000.pb",
+                "000000000005-0001.pb",
+                "000000000005-0002.pb"
+            ]
+        );
+        for (payload, name) in (1..=3u8).zip(&files) {
+            assert_eq!(frames(&dir.read("slots", name)), [vec![payload; 6]]);
+        }
+    }
+
+    #[test]
+    fn rotates_files_by_slot_range() {
+        let dir = TempDir::new("slots");
+        let sink = FileSink::new(&FileSinkConfig {
+            slots_per_file: Some(10),
+            ..dir.config()
+        })
+        .unwrap();
+        sink.send(&record(5, &[5])).unwrap();
+        sink.send(&record(12, &[12])).unwrap();
+        // Late updates for an older range stay in the current file.
+        sink.send(&record(9, &[9])).unwrap();
+        sink.send(&record(20, &[20])).unwrap();
+        drop(sink);
+
+        assert_eq!(
+            dir.files("slots"),
+            [
+                "000000000005-0000.pb",
+                "000000000012-0000.pb",
+                "000000000020-0000.pb"
+            ]
+        );
+        assert_eq!(
+            frames(&dir.read("slots", "000000000012-0000.pb")),
+            [vec![12], vec![9]]
+        );
+    }
+
+    #[test]
+    fn keeps_open_files_partial_until_closed() {
+        let dir = TempDir::new("partial");
+        let sink = FileSink::new(&FileSinkConfig {
+            slots_per_file: Some(10),
+            ..dir.config()
+        })
+        .unwrap();
+        sink.send(&record(5, &[5])).unwrap();
+        assert_eq!(dir.files("slots"), ["000000000005-0000.pb.partial"]);
+
+        sink.send(&record(15, &[15])).unwrap();
+        assert_eq!(
+            dir.files("slots"),
+            ["000000000005-0000.pb", "000000000015-0000.pb.partial"]
+        );
+
+        drop(sink);
+        assert_eq!(
+            dir.files("slots"),
+            ["000000000005-0000.pb", "000000000015-0000.pb"]
+        );
+    }
+
+    #[test]
+    fn zstd_files_decompress_to_frames() {
+        let dir = TempDir::new("zstd");
+        let sink = FileSink::new(&FileSinkConfig {
+            compression: FileCompression::Zstd,
+            ..dir.config()
+        })
+        .unwrap();
+        let payloads = [vec![1; 200], vec![2; 300], Vec::new()];
+        for payload in &payloads {
+            sink.send(&record(5, payload)).unwrap();
+        }
+        drop(sink);
+
+        assert_eq!(dir.files("slots"), ["000000000005-0000.pb.zst"]);
+        let compressed = dir.read("slots", "000000000005-0000.pb.zst");
+        assert!(compressed.len() < 500);
+        let data = zstd::stream::decode_all(compressed.as_slice()).unwrap();
+        assert_eq!(frames(&data), payloads);
+    }
+
+    #[test]
+    fn rejects_unwrapped_records() {
+        let dir = TempDir::new("unwrapped");
+        let sink = FileSink::new(&dir.config()).unwrap();
+        let mut unwrapped = record(5, &[1]);
+        unwrapped.tag = DeliveryTag::new(EventKind::Slot, MessageFormat::Protobuf);
+        assert!(sink.send(&unwrapped).is_err());
+    }
+}
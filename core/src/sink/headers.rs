@@ -0,0 +1,142 @@
+use {
+    crate::{DeliveryTag, HeadersConfig, SCHEMA_VERSION},
+    rdkafka::message::{Header, OwnedHeaders},
+    std::time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Event kind of the record: `account`, `slot`, `transaction`, `block_metadata` or `entry`.
+pub const HEADER_EVENT: &str = "heimdall-event";
+/// `true` if the payload is a `MessageWrapper`, `false` otherwise.
+pub const HEADER_WRAPPED: &str = "heimdall-wrapped";
+/// Encoding of the payload: `protobuf`, `protobuf-wrapped`, `json` or `confluent-protobuf`.
+pub const HEADER_FORMAT: &str = "heimdall-format";
+/// Version of `heimdall.proto` the payload was encoded with.
+pub const HEADER_SCHEMA_VERSION: &str = "heimdall-schema-version";
+pub const HEADER_SLOT: &str = "heimdall-slot";
+pub const HEADER_HOST: &str = "heimdall-host";
+pub const HEADER_VALIDATOR: &str = "heimdall-validator";
+/// Milliseconds since the Unix epoch at which the record was handed to the producer.
+pub const HEADER_TIMESTAMP: &str = "heimdall-timestamp";
+
+/// Builds the headers attached to every Kafka record. All values are UTF-8 text.
+#[derive(Debug, Clone)]
+pub struct RecordHeaders {
+    host_id: Option<String>,
+    validator_identity: Option<String>,
+}
+
+impl RecordHeaders {
+    /// Returns None if headers are disabled.
+    pub fn new(config: &HeadersConfig) -> Option<Self> {
+        config.enabled.then(|| Self {
+            host_id: config.host_id.clone(),
+            validator_identity: config.validator_identity.clone(),
+        })
+    }
+
+    /// The slot header is left out if the slot is unknown.
+    pub fn build(&self, tag: DeliveryTag, slot: Option<u64>) -> OwnedHeaders {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_millis());
+        let mut headers = OwnedHeaders::new_with_capacity(8)
+            .insert(Header {
+                key: HEADER_EVENT,
+                value: Some(tag.kind.as_str()),
+            })
+            .insert(Header {
+                key: HEADER_WRAPPED,
+                value: Some(if tag.is_wrapped() { "true" } else { "false" }),
+            })
+            .insert(Header {
+                key: HEADER_FORMAT,
+                value: Some(tag.format.as_str()),
+            })
+            .insert(Header {
+                key: HEADER_SCHEMA_VERSION,
+                value: Some(&SCHEMA_VERSION.to_string()),
+            })
+            .insert(Header {
+                key: HEADER_TIMESTAMP,
+                value: Some(&timestamp.to_string()),
+            });
+        if let Some(slot) = slot {
+            headers = headers.insert(Header {
+                key: HEADER_SLOT,
+                value: Some(&slot.to_string()),
+            });
+        }
+        if let Some(host_id) = &self.host_id {
+            headers = headers.insert(Header {
+                key: HEADER_HOST,
+                value: Some(host_id),
+            });
+        }
+        if let Some(identity) = &self.validator_identity {
+            headers = headers.insert(Header {
+                key: HEADER_VALIDATOR,
+                value: Some(identity),
+            });
+        }
+        headers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{EventKind, MessageFormat},
+        rdkafka::message::Headers,
+    };
+
+    fn value<'a>(headers: &'a OwnedHeaders, key: &str) -> Option<&'a str> {
+        headers
+            .iter()
+            .find(|header| header.key == key)
+            .and_then(|header| std::str::from_utf8(header.value?).ok())
+    }
+
+    #[test]
+    fn builds_headers_from_the_config() {
+        let headers = RecordHeaders::new(&HeadersConfig {
+            enabled: true,
+            host_id: Some("host-1".to_owned()),
+            validator_identity: None,
+        })
+        .unwrap();
+
+        let built = headers.build(
+            DeliveryTag::new(EventKind::Transaction, MessageFormat::ProtobufWrapped),
+            Some(42),
+        );
+        assert_eq!(value(&built, HEADER_EVENT), Some("transaction"));
+        assert_eq!(value(&built, HEADER_WRAPPED), Some("true"));
+        assert_eq!(value(&built, HEADER_FORMAT), Some("protobuf-wrapped"));
+        assert_eq!(
+            value(&built, HEADER_SCHEMA_VERSION),
+            Some(SCHEMA_VERSION.to_string().as_str())
+        );
+        assert!(value(&built, HEADER_TIMESTAMP).is_some());
+        assert_eq!(value(&built, HEADER_HOST), Some("host-1"));
+        assert_eq!(value(&built, HEADER_VALIDATOR), None);
+        assert_eq!(value(&built, HEADER_SLOT), Some("42"));
+
+        let built = headers.build(
+            DeliveryTag::new(EventKind::Slot, MessageFormat::Protobuf),
+            None,
+        );
+        assert_eq!(value(&built, HEADER_WRAPPED), Some("false"));
+        assert_eq!(value(&built, HEADER_FORMAT), Some("protobuf"));
+        assert_eq!(value(&built, HEADER_SLOT), None);
+    }
+
+    #[test]
+    fn disabled_headers_are_not_built() {
+        let config = HeadersConfig {
+            enabled: false,
+            ..Default::default()
+        };
+        assert!(RecordHeaders::new(&config).is_none());
+    }
+}
@@ -0,0 +1,393 @@
+use {
+    super::{EventSink, Record, RecordHeaders, SinkResult, SlotTransactions},
+    crate::{Delivery, HeimdallProducerContext, KafkaSinkConfig, SegmentReplay, SlotStatus, Spool},
+    log::{debug, error, info, warn},
+    rdkafka::{
+        error::{KafkaError, RDKafkaErrorCode},
+        producer::{BaseRecord, Producer as _, ThreadedProducer},
+    },
+    std::{
+        io,
+        sync::{
+            Arc,
+            atomic::{AtomicBool, Ordering},
+        },
+        thread::{self, JoinHandle},
+        time::Duration,
+    },
+};
+
+pub type Producer = ThreadedProducer<HeimdallProducerContext>;
+
+/// Publishes records to Kafka, spooling them to disk while the brokers are unreachable.
+pub struct KafkaSink {
+    name: String,
+    producer: Arc<Producer>,
+    headers: Option<RecordHeaders>,
+    transactions: Option<SlotTransactions>,
+    spool: Option<Arc<Spool>>,
+    drainer: Option<Drainer>,
+    shutdown_timeout: Duration,
+}
+
+struct Drainer {
+    exit: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+}
+
+impl KafkaSink {
+    pub fn new(config: &KafkaSinkConfig, shutdown_timeout: Duration) -> SinkResult<Self> {
+        let dead_letter = config
+            .dead_letter
+            .as_ref()
+            .map(|dead_letter_config| {
+                Spool::open(dead_letter_config).inspect_err(|error| {
+                    error!(
+                        "Failed to open dead-letter store at {:?}: {error:?}",
+                        dead_letter_config.path
+                    )
+                })
+            })
+            .transpose()?;
+        let spool = config
+            .spool
+            .as_ref()
+            .map(|spool_config| {
+                Spool::open(spool_config)
+                    .map(Arc::new)
+                    .inspect_err(|error| {
+                        error!("Failed to open spool at {:?}: {error:?}", spool_config.path)
+                    })
+            })
+            .transpose()?;
+
+        // Records that expire while the brokers are down go back to the spool.
+        let context = HeimdallProducerContext::new(spool.clone(), dead_letter);
+        let producer = config.producer(context).inspect_err(|error| {
+            error!(
+                "Failed to create kafka producer for sink {}: {error:?}",
+                config.name
+            )
+        })?;
+        info!("Created Kafka ThreadedProducer for sink {}", config.name);
+
+        let producer = Arc::new(producer);
+        let headers = RecordHeaders::new(&config.headers);
+        let transactions = config
+            .transactions
+            .as_ref()
+            .map(|transactions_config| {
+                SlotTransactions::new(Arc::clone(&producer), headers.clone(), transactions_config)
+                    .inspect_err(|error| {
+                        error!(
+                            "Failed to initialize transactions for sink {}: {error:?}",
+                            config.name
+                        )
+                    })
+            })
+            .transpose()?;
+        let drainer = config
+            .spool
+            .as_ref()
+            .zip(spool.as_ref())
+            .map(|(spool_config, spool)| {
+                Drainer::spawn(
+                    Arc::clone(&producer),
+                    headers.clone(),
+                    Arc::clone(spool),
+                    Duration::from_millis(spool_config.drain_interval_ms),
+                )
+            });
+
+        Ok(Self {
+            name: config.name.clone(),
+            producer,
+            headers,
+            transactions,
+            spool,
+            drainer,
+            shutdown_timeout,
+        })
+    }
+
+    pub fn in_flight_count(&self) -> i32 {
+        self.producer.in_flight_count()
+    }
+}
+
+impl EventSink for KafkaSink {
+    fn send(&self, record: &Record<'_>) -> SinkResult<()> {
+        let Record {
+            topic,
+            tag,
+            slot,
+            key,
+            partition,
+            payload,
+            ..
+        } = *record;
+        let kind = tag.kind.as_str();
+
+        if let Some(transactions) = &self.transactions {
+            transactions.buffer(record)?;
+            debug!(
+                "Buffered {} event of slot {} for topic: {}",
+                kind, slot, topic
+            );
+            return Ok(());
+        }
+
+        // Once anything is spooled, newer records queue up behind it to keep ordering
+        // until the drainer reaches the last segment.
+        if let Some(spool) = self.spool.as_ref().filter(|spool| spool.is_pending()) {
+            return match spool.append(record) {
+                Ok(()) => {
+                    debug!("Spooled {} event for topic: {}", kind, topic);
+                    Ok(())
+                }
+                Err(e) => {
+                    error!(
+                        "Failed to spool {} event for topic {}: {:?}",
+                        kind, topic, e
+                    );
+                    Err(e.into())
+                }
+            };
+        }
+
+        let mut kafka_record =
+            BaseRecord::<[u8], _, _>::with_opaque_to(topic, Delivery::new(record))
+                .key(key)
+                .payload(payload);
+        kafka_record.partition = partition;
+        kafka_record.headers = self
+            .headers
+            .as_ref()
+            .map(|headers| headers.build(tag, Some(slot)));
+
+        match self.producer.send(kafka_record) {
+            Ok(_) => {
+                debug!("Successfully sent {} event to topic: {}", kind, topic);
+                Ok(())
+            }
+            Err((e, _)) => match &self.spool {
+                Some(spool) => match spool.append(record) {
+                    Ok(()) => {
+                        warn!(
+                            "Spooled {} event for topic {} after send error: {:?}",
+                            kind, topic, e
+                        );
+                        Ok(())
+                    }
+                    Err(spool_err) => {
+                        error!(
+                            "Failed to send {} event to topic {}: {:?}, spooling failed: {:?}",
+                            kind, topic, e, spool_err
+                        );
+                        Err(e.into())
+                    }
+                },
+                None => {
+                    error!("Failed to send {} event to topic {}: {:?}", kind, topic, e);
+                    Err(e.into())
+                }
+            },
+        }
+    }
+
+    fn spill(&self, record: &Record<'_>) -> SinkResult<()> {
+        let spool = self
+            .spool
+            .as_ref()
+            .ok_or_else(|| io::Error::other("spool is not configured"))?;
+        spool.append(record)?;
+        Ok(())
+    }
+
+    fn flush(&self, timeout: Duration) -> SinkResult<()> {
+        self.producer.flush(timeout)?;
+        Ok(())
+    }
+
+    fn slot_status(&self, slot: u64, status: SlotStatus) -> SinkResult<()> {
+        match &self.transactions {
+            Some(transactions) => transactions.slot_status(slot, status),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for KafkaSink {
+    fn drop(&mut self) {
+        debug!("Shutting down Kafka sink {}", self.name);
+        if let Some(drainer) = self.drainer.take() {
+            drainer.stop();
+        }
+        if let Some(mut transactions) = self.transactions.take() {
+            transactions.stop();
+            let uncommitted = transactions.uncommitted();
+            if uncommitted > 0 {
+                warn!(
+                    "Kafka sink {} discarded {} records of slots that were not committed",
+                    self.name, uncommitted
+                );
+            }
+        }
+
+        match self.producer.flush(self.shutdown_timeout) {
+            Ok(()) => debug!("Kafka sink {} shutdown completed successfully", self.name),
+            Err(e) => {
+                warn!(
+                    "Kafka sink {} shutdown completed with errors after {:?}: {:?}",
+                    self.name, self.shutdown_timeout, e
+                );
+            }
+        }
+        self.producer.context().stats().log_summary();
+    }
+}
+
+impl Drainer {
+    const METADATA_TIMEOUT: Duration = Duration::from_secs(5);
+    const QUEUE_FULL_BACKOFF: Duration = Duration::from_millis(100);
+    const DELIVERY_WAIT: Duration = Duration::from_millis(500);
+
+    fn spawn(
+        producer: Arc<Producer>,
+        headers: Option<RecordHeaders>,
+        spool: Arc<Spool>,
+        interval: Duration,
+    ) -> Self {
+        let exit = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let exit = Arc::clone(&exit);
+            thread::Builder::new()
+                .name("heimdallSpool".to_owned())
+                .spawn(move || Self::run(&producer, headers.as_ref(), &spool, &exit, interval))
+                .expect("failed to spawn spool drainer")
+        };
+        Self { exit, handle }
+    }
+
+    fn stop(self) {
+        self.exit.store(true, Ordering::Relaxed);
+        if self.handle.join().is_err() {
+            error!("Spool drainer panicked during shutdown");
+        }
+    }
+
+    fn run(
+        producer: &Producer,
+        headers: Option<&RecordHeaders>,
+        spool: &Spool,
+        exit: &AtomicBool,
+        interval: Duration,
+    ) {
+        while !exit.load(Ordering::Relaxed) {
+            if spool.is_empty() {
+                thread::sleep(interval);
+                continue;
+            }
+
+            if let Err(e) = producer
+                .client()
+                .fetch_metadata(None, Self::METADATA_TIMEOUT)
+            {
+                debug!("Kafka still unreachable, keeping records spooled: {:?}", e);
+                thread::sleep(interval);
+                continue;
+            }
+
+            match Self::drain_segment(producer, headers, spool, exit) {
+                Ok(true) => {}
+                Ok(false) => thread::sleep(interval),
+                Err(e) => {
+                    error!("Failed to replay spool segment: {:?}", e);
+                    thread::sleep(interval);
+                }
+            }
+        }
+    }
+
+    /// Replays the oldest segment and deletes it once every record in it was
+    /// delivered. Returns false if it was interrupted by shutdown.
+    ///
+    /// A segment that fails part way is replayed again from the start, so records
+    /// can reach Kafka more than once but are never lost.
+    fn drain_segment(
+        producer: &Producer,
+        headers: Option<&RecordHeaders>,
+        spool: &Spool,
+        exit: &AtomicBool,
+    ) -> io::Result<bool> {
+        let Some(id) = spool.next_segment()? else {
+            return Ok(false);
+        };
+
+        let replay = Arc::new(SegmentReplay::default());
+        let mut replayed = 0u64;
+        for record in spool.read_segment(id)? {
+            let record = record?;
+            loop {
+                let mut kafka_record = BaseRecord::<[u8], _, _>::with_opaque_to(
+                    &record.topic,
+                    Box::new(Delivery {
+                        tag: record.tag,
+                        slot: record.slot,
+                        partition: record.partition,
+                        replay: Some(Arc::clone(&replay)),
+                        transactional: false,
+                    }),
+                )
+                .key(record.key.as_slice())
+                .payload(record.payload.as_slice());
+                kafka_record.partition = record.partition;
+                kafka_record.headers =
+                    headers.map(|headers| headers.build(record.tag, Some(record.slot)));
+                replay.sent();
+                match producer.send(kafka_record) {
+                    Ok(()) => break,
+                    Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), _)) => {
+                        replay.unsent();
+                        // The segment stays until it is fully delivered, so it is
+                        // safe to bail out here.
+                        if exit.load(Ordering::Relaxed) {
+                            return Ok(false);
+                        }
+                        thread::sleep(Self::QUEUE_FULL_BACKOFF);
+                    }
+                    Err((e, _)) => {
+                        replay.unsent();
+                        return Err(io::Error::other(format!(
+                            "failed to replay record of segment {id} for topic {}: {e:?}",
+                            record.topic
+                        )));
+                    }
+                }
+            }
+            replayed += 1;
+        }
+
+        let failed = loop {
+            if let Some(failed) = replay.wait(Self::DELIVERY_WAIT) {
+                break failed;
+            }
+            if exit.load(Ordering::Relaxed) {
+                return Ok(false);
+            }
+        };
+        if failed > 0 {
+            return Err(io::Error::other(format!(
+                "{failed} of {replayed} records of segment {id} were not delivered"
+            )));
+        }
+
+        spool.remove_segment(id)?;
+        spool
+            .stats()
+            .replayed
+            .fetch_add(replayed, Ordering::Relaxed);
+        info!("Replayed {} spooled records from segment {}", replayed, id);
+        Ok(true)
+    }
+}
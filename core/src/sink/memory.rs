@@ -0,0 +1,66 @@
+use {
+    super::{EventSink, Record, SinkResult},
+    crate::DeliveryTag,
+    std::sync::Mutex,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OwnedRecord {
+    pub topic: String,
+    pub tag: DeliveryTag,
+    pub slot: u64,
+    pub key: Vec<u8>,
+    pub partition: Option<i32>,
+    pub payload: Vec<u8>,
+}
+
+impl From<&Record<'_>> for OwnedRecord {
+    fn from(record: &Record<'_>) -> Self {
+        Self {
+            topic: record.topic.to_owned(),
+            tag: record.tag,
+            slot: record.slot,
+            key: record.key.to_vec(),
+            partition: record.partition,
+            payload: record.payload.to_vec(),
+        }
+    }
+}
+
+impl OwnedRecord {
+    pub fn as_record(&self) -> Record<'_> {
+        Record {
+            topic: &self.topic,
+            tag: self.tag,
+            slot: self.slot,
+            key: &self.key,
+            partition: self.partition,
+            payload: &self.payload,
+            event: None,
+        }
+    }
+}
+
+/// Keeps every record in memory, so tests can assert exactly what was published.
+#[derive(Debug, Default)]
+pub struct MemorySink {
+    records: Mutex<Vec<OwnedRecord>>,
+}
+
+impl MemorySink {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Removes and returns all records received so far.
+    pub fn take(&self) -> Vec<OwnedRecord> {
+        std::mem::take(&mut self.records.lock().unwrap())
+    }
+}
+
+impl EventSink for MemorySink {
+    fn send(&self, record: &Record<'_>) -> SinkResult<()> {
+        self.records.lock().unwrap().push(record.into());
+        Ok(())
+    }
+}
@@ -0,0 +1,63 @@
+use {
+    crate::{DeliveryTag, SlotStatus, message_wrapper::EventMessage},
+    std::{error::Error, time::Duration},
+};
+
+mod file;
+mod headers;
+mod kafka;
+mod memory;
+mod socket;
+mod transactions;
+
+pub use {
+    file::FileSink,
+    headers::{
+        HEADER_EVENT, HEADER_FORMAT, HEADER_HOST, HEADER_SCHEMA_VERSION, HEADER_SLOT,
+        HEADER_TIMESTAMP, HEADER_VALIDATOR, HEADER_WRAPPED, RecordHeaders,
+    },
+    kafka::{KafkaSink, Producer},
+    memory::{MemorySink, OwnedRecord},
+    socket::SocketSink,
+    transactions::{SlotTransactions, TransactionalProducer},
+};
+
+pub type SinkResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+/// An encoded event on its way to a sink.
+#[derive(Debug, Clone, Copy)]
+pub struct Record<'a> {
+    pub topic: &'a str,
+    pub tag: DeliveryTag,
+    pub slot: u64,
+    pub key: &'a [u8],
+    /// Partition the record must go to, left to the partitioner if None.
+    pub partition: Option<i32>,
+    pub payload: &'a [u8],
+    /// The event the payload was encoded from, unset for records read back from a
+    /// spool or a delivery report.
+    pub event: Option<&'a EventMessage>,
+}
+
+/// A destination for encoded events.
+///
+/// `Publisher` encodes events once and hands the resulting records to the sink a
+/// filter routes to, so new backends only need to implement this trait.
+pub trait EventSink: Send + Sync {
+    fn send(&self, record: &Record<'_>) -> SinkResult<()>;
+
+    /// Stores a record locally to be delivered later, used when the pipeline overflows.
+    fn spill(&self, _record: &Record<'_>) -> SinkResult<()> {
+        Err("sink does not support spilling".into())
+    }
+
+    fn flush(&self, _timeout: Duration) -> SinkResult<()> {
+        Ok(())
+    }
+
+    /// Reports a slot status once every record queued before it was reported has been
+    /// passed to `send`.
+    fn slot_status(&self, _slot: u64, _status: SlotStatus) -> SinkResult<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,463 @@
+use {
+    super::{EventSink, Record, SinkResult},
+    crate::{ConfigFilter, Filter, SocketSinkConfig},
+    log::{debug, info, warn},
+    std::{
+        fs,
+        io::{self, BufWriter, ErrorKind, Read, Write},
+        net::Shutdown,
+        os::unix::{
+            fs::{FileTypeExt, PermissionsExt},
+            net::{UnixListener, UnixStream},
+        },
+        path::PathBuf,
+        sync::{
+            Arc, Mutex,
+            atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+            mpsc::{self, Receiver, SyncSender, TrySendError},
+        },
+        thread::{self, JoinHandle},
+        time::Duration,
+    },
+};
+
+/// Largest subscription a reader may send.
+const MAX_SUBSCRIPTION_BYTES: usize = 1024 * 1024;
+
+/// Serves the `MessageWrapper` stream to readers on the same host over a Unix socket.
+///
+/// A reader connects and sends its subscription as a little-endian `u32` length
+/// followed by a JSON `ConfigFilter`. It then receives every message its filter
+/// accepts, framed like `prost::Message::encode_length_delimited`. The topic fields
+/// of the subscription select events by the topic the plugin published them under.
+///
+/// Each reader has a bounded buffer; a reader that lets it fill up is disconnected
+/// instead of holding up the publish workers.
+pub struct SocketSink {
+    name: String,
+    path: PathBuf,
+    shared: Arc<Shared>,
+    acceptor: Option<JoinHandle<()>>,
+}
+
+struct Shared {
+    readers: Mutex<Vec<Reader>>,
+    connections: AtomicUsize,
+    next_id: AtomicU64,
+    exit: AtomicBool,
+    max_readers: usize,
+    reader_buffer: usize,
+    handshake_timeout: Duration,
+}
+
+struct Reader {
+    id: u64,
+    filter: Filter,
+    sender: SyncSender<Arc<[u8]>>,
+    stream: UnixStream,
+}
+
+impl SocketSink {
+    const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+    pub fn new(config: &SocketSinkConfig) -> io::Result<Self> {
+        // A socket left behind by an unclean shutdown would make bind fail, but
+        // anything else at that path is not ours to delete.
+        match fs::symlink_metadata(&config.path) {
+            Ok(metadata) if metadata.file_type().is_socket() => fs::remove_file(&config.path)?,
+            Ok(_) => {
+                return Err(io::Error::new(
+                    ErrorKind::AlreadyExists,
+                    format!("{:?} exists and is not a socket", config.path),
+                ));
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        let listener = UnixListener::bind(&config.path)?;
+        fs::set_permissions(&config.path, fs::Permissions::from_mode(config.mode))?;
+        listener.set_nonblocking(true)?;
+        info!(
+            "Serving events for sink {} on {:?} with mode {:o}",
+            config.name, config.path, config.mode
+        );
+
+        let shared = Arc::new(Shared {
+            readers: Mutex::new(Vec::new()),
+            connections: AtomicUsize::new(0),
+            next_id: AtomicU64::new(0),
+            exit: AtomicBool::new(false),
+            max_readers: config.max_readers,
+            reader_buffer: config.reader_buffer.max(1),
+            handshake_timeout: Duration::from_millis(config.handshake_timeout_ms),
+        });
+        let acceptor = {
+            let shared = Arc::clone(&shared);
+            thread::Builder::new()
+                .name("heimdallSockAcc".to_owned())
+                .spawn(move || Self::accept(&listener, &shared))?
+        };
+
+        Ok(Self {
+            name: config.name.clone(),
+            path: config.path.clone(),
+            shared,
+            acceptor: Some(acceptor),
+        })
+    }
+
+    /// Returns the number of readers that have completed their subscription.
+    pub fn reader_count(&self) -> usize {
+        self.shared.readers.lock().unwrap().len()
+    }
+
+    fn accept(listener: &UnixListener, shared: &Arc<Shared>) {
+        while !shared.exit.load(Ordering::Relaxed) {
+            let stream = match listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                    thread::sleep(Self::ACCEPT_POLL_INTERVAL);
+                    continue;
+                }
+                Err(e) => {
+                    warn!("Failed to accept socket reader: {:?}", e);
+                    thread::sleep(Self::ACCEPT_POLL_INTERVAL);
+                    continue;
+                }
+            };
+
+            if shared.connections.load(Ordering::Relaxed) >= shared.max_readers {
+                warn!(
+                    "Rejecting socket reader, {} readers already connected",
+                    shared.max_readers
+                );
+                continue;
+            }
+
+            let id = shared.next_id.fetch_add(1, Ordering::Relaxed);
+            shared.connections.fetch_add(1, Ordering::Relaxed);
+            let spawned = {
+                let shared = Arc::clone(shared);
+                thread::Builder::new()
+                    .name(format!("heimdallSock{id:02}"))
+                    .spawn(move || {
+                        if let Err(e) = Self::serve(&shared, id, stream) {
+                            debug!("Socket reader {} disconnected: {:?}", id, e);
+                        }
+                        shared.remove(id);
+                        shared.connections.fetch_sub(1, Ordering::Relaxed);
+                    })
+            };
+            if let Err(e) = spawned {
+                warn!("Failed to spawn thread for socket reader {}: {:?}", id, e);
+                shared.connections.fetch_sub(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn serve(shared: &Shared, id: u64, stream: UnixStream) -> io::Result<()> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(shared.handshake_timeout))?;
+        let filter = Self::read_subscription(&stream)?;
+
+        let (sender, receiver) = mpsc::sync_channel(shared.reader_buffer);
+        shared.readers.lock().unwrap().push(Reader {
+            id,
+            filter,
+            sender,
+            stream: stream.try_clone()?,
+        });
+        info!("Socket reader {} subscribed", id);
+
+        Self::write_frames(receiver, stream)
+    }
+
+    fn read_subscription(mut stream: &UnixStream) -> io::Result<Filter> {
+        let mut len = [0; 4];
+        stream.read_exact(&mut len)?;
+        let len = u32::from_le_bytes(len) as usize;
+        if len > MAX_SUBSCRIPTION_BYTES {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("subscription of {len} bytes is too large"),
+            ));
+        }
+
+        let mut buf = vec![0; len];
+        stream.read_exact(&mut buf)?;
+        let config: ConfigFilter = serde_json::from_slice(&buf)?;
+        config
+            .validate()
+            .map_err(|problems| io::Error::new(ErrorKind::InvalidData, problems.join("; ")))?;
+        Ok(Filter::new(&config))
+    }
+
+    fn write_frames(receiver: Receiver<Arc<[u8]>>, stream: UnixStream) -> io::Result<()> {
+        let mut writer = BufWriter::new(stream);
+        while let Ok(frame) = receiver.recv() {
+            writer.write_all(&frame)?;
+            // Batch whatever else is already queued into the same flush.
+            while let Ok(frame) = receiver.try_recv() {
+                writer.write_all(&frame)?;
+            }
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl Shared {
+    fn remove(&self, id: u64) {
+        self.readers
+            .lock()
+            .unwrap()
+            .retain(|reader| reader.id != id);
+    }
+}
+
+impl EventSink for SocketSink {
+    fn send(&self, record: &Record<'_>) -> SinkResult<()> {
+        if !record.tag.is_wrapped() {
+            return Err("socket sinks only accept the protobuf-wrapped format".into());
+        }
+
+        let mut readers = self.shared.readers.lock().unwrap();
+        if readers.is_empty() {
+            return Ok(());
+        }
+
+        let Some(message) = record.event else {
+            return Err("socket sinks need the event a record was encoded from".into());
+        };
+
+        let mut frame: Option<Arc<[u8]>> = None;
+        readers.retain(|reader| {
+            if !reader.filter.wants_message(record.topic, message) {
+                return true;
+            }
+
+            let frame = frame
+                .get_or_insert_with(|| {
+                    let mut buf = Vec::with_capacity(
+                        record.payload.len() + prost::length_delimiter_len(record.payload.len()),
+                    );
+                    prost::encode_length_delimiter(record.payload.len(), &mut buf)
+                        .expect("vec has capacity");
+                    buf.extend_from_slice(record.payload);
+                    buf.into()
+                })
+                .clone();
+            match reader.sender.try_send(frame) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => {
+                    warn!(
+                        "Disconnecting socket reader {} on sink {}, it fell {} messages behind",
+                        reader.id, self.name, self.shared.reader_buffer
+                    );
+                    let _ = reader.stream.shutdown(Shutdown::Both);
+                    false
+                }
+                Err(TrySendError::Disconnected(_)) => false,
+            }
+        });
+        Ok(())
+    }
+}
+
+impl Drop for SocketSink {
+    fn drop(&mut self) {
+        debug!("Shutting down socket sink {}", self.name);
+        self.shared.exit.store(true, Ordering::Relaxed);
+        if let Some(acceptor) = self.acceptor.take()
+            && acceptor.join().is_err()
+        {
+            warn!("Socket acceptor for sink {} panicked", self.name);
+        }
+
+        for reader in self.shared.readers.lock().unwrap().drain(..) {
+            let _ = reader.stream.shutdown(Shutdown::Both);
+        }
+        if let Err(e) = fs::remove_file(&self.path) {
+            debug!("Failed to remove socket {:?}: {:?}", self.path, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            DeliveryTag, EventKind, MessageFormat, SlotStatusEvent, message_wrapper::EventMessage,
+        },
+        std::time::Instant,
+    };
+
+    fn socket_path(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("heimdall-{name}-{}.sock", std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn config(path: PathBuf) -> SocketSinkConfig {
+        SocketSinkConfig {
+            name: "socket".to_owned(),
+            path,
+            max_readers: 1,
+            reader_buffer: 1,
+            handshake_timeout_ms: 100,
+            mode: 0o600,
+        }
+    }
+
+    fn subscribe(path: &PathBuf, subscription: &[u8]) -> UnixStream {
+        let mut stream = UnixStream::connect(path).unwrap();
+        stream
+            .write_all(&(subscription.len() as u32).to_le_bytes())
+            .unwrap();
+        stream.write_all(subscription).unwrap();
+        stream
+    }
+
+    fn wait_for_readers(sink: &SocketSink, count: usize) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while sink.reader_count() != count {
+            assert!(Instant::now() < deadline, "timed out waiting for readers");
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    fn slot_event(slot: u64) -> EventMessage {
+        EventMessage::Slot(SlotStatusEvent {
+            slot,
+            ..Default::default()
+        })
+    }
+
+    fn record<'a>(topic: &'a str, payload: &'a [u8], event: &'a EventMessage) -> Record<'a> {
+        Record {
+            topic,
+            tag: DeliveryTag::new(EventKind::Slot, MessageFormat::ProtobufWrapped),
+            slot: 1,
+            key: b"key",
+            partition: None,
+            payload,
+            event: Some(event),
+        }
+    }
+
+    fn read_frame(stream: &mut UnixStream) -> Vec<u8> {
+        let mut delimiter = Vec::new();
+        loop {
+            let mut byte = [0; 1];
+            stream.read_exact(&mut byte).unwrap();
+            delimiter.push(byte[0]);
+            if byte[0] & 0x80 == 0 {
+                break;
+            }
+        }
+        let len = prost::decode_length_delimiter(delimiter.as_slice()).unwrap();
+        let mut frame = vec![0; len];
+        stream.read_exact(&mut frame).unwrap();
+        frame
+    }
+
+    #[test]
+    fn replaces_stale_socket_only() {
+        let path = socket_path("stale");
+
+        // Left behind like after a crash.
+        drop(UnixListener::bind(&path).unwrap());
+        let sink = SocketSink::new(&config(path.clone())).unwrap();
+        drop(sink);
+        assert!(!path.exists());
+
+        fs::write(&path, b"not a socket").unwrap();
+        let error = SocketSink::new(&config(path.clone())).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::AlreadyExists);
+        assert_eq!(fs::read(&path).unwrap(), b"not a socket");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn restricts_socket_permissions() {
+        let path = socket_path("mode");
+        let sink = SocketSink::new(&config(path.clone())).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        drop(sink);
+
+        let sink = SocketSink::new(&SocketSinkConfig {
+            mode: 0o660,
+            ..config(path.clone())
+        })
+        .unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o660);
+        drop(sink);
+    }
+
+    #[test]
+    fn rejects_invalid_subscriptions() {
+        let path = socket_path("invalid");
+        let sink = SocketSink::new(&config(path.clone())).unwrap();
+
+        for subscription in [
+            &b"not json"[..],
+            br#"{"slot_status_topic": "slots", "unknown": true}"#,
+            br#"{"transaction_topic": "txs", "account_include": ["not a pubkey"]}"#,
+        ] {
+            let mut stream = subscribe(&path, subscription);
+            // The sink hangs up instead of registering the reader.
+            assert_eq!(stream.read(&mut [0; 1]).unwrap(), 0);
+            assert_eq!(sink.reader_count(), 0);
+        }
+
+        let mut stream = UnixStream::connect(&path).unwrap();
+        stream
+            .write_all(&(MAX_SUBSCRIPTION_BYTES as u32 + 1).to_le_bytes())
+            .unwrap();
+        assert_eq!(stream.read(&mut [0; 1]).unwrap(), 0);
+        assert_eq!(sink.reader_count(), 0);
+    }
+
+    #[test]
+    fn streams_events_the_subscription_wants() {
+        let path = socket_path("stream");
+        let sink = SocketSink::new(&config(path.clone())).unwrap();
+        let mut stream = subscribe(&path, br#"{"slot_status_topic": "slots"}"#);
+        wait_for_readers(&sink, 1);
+
+        let event = slot_event(7);
+        sink.send(&record("other-slots", b"skipped", &event))
+            .unwrap();
+        sink.send(&record("slots", b"wanted", &event)).unwrap();
+        assert_eq!(read_frame(&mut stream), b"wanted");
+
+        let mut unwrapped = record("slots", b"raw", &event);
+        unwrapped.tag = DeliveryTag::new(EventKind::Slot, MessageFormat::Protobuf);
+        assert!(sink.send(&unwrapped).is_err());
+    }
+
+    #[test]
+    fn disconnects_readers_that_fall_behind() {
+        let path = socket_path("slow");
+        let sink = SocketSink::new(&config(path.clone())).unwrap();
+        // Subscribed, but never reads.
+        let _stream = subscribe(&path, br#"{"slot_status_topic": "slots"}"#);
+        wait_for_readers(&sink, 1);
+
+        // Large enough to fill the socket buffer, the writer thread and the channel.
+        let payload = vec![0; 1 << 20];
+        let event = slot_event(7);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while sink.reader_count() > 0 {
+            assert!(
+                Instant::now() < deadline,
+                "slow reader was not disconnected"
+            );
+            sink.send(&record("slots", &payload, &event)).unwrap();
+        }
+    }
+}
@@ -0,0 +1,652 @@
+use {
+    super::{OwnedRecord, Record, RecordHeaders, SinkResult, kafka::Producer},
+    crate::{CommitStatus, Delivery, SlotStatus, TransactionsConfig},
+    log::{debug, error, info, warn},
+    rdkafka::{
+        error::{KafkaError, KafkaResult, RDKafkaErrorCode},
+        producer::{BaseRecord, Producer as _, ThreadedProducer},
+    },
+    std::{
+        collections::{BTreeMap, BTreeSet},
+        sync::{
+            Arc, Mutex, OnceLock,
+            mpsc::{self, Receiver, Sender},
+        },
+        thread::{self, JoinHandle},
+        time::Duration,
+    },
+};
+
+/// Slots this far behind the last committed one are assumed to be on an abandoned
+/// fork, and their records are dead-lettered.
+const ABANDONED_SLOT_LAG: u64 = 150;
+const QUEUE_FULL_BACKOFF: Duration = Duration::from_millis(100);
+/// Attempts to commit a slot before its records are put back for the next one.
+const COMMIT_ATTEMPTS: usize = 3;
+
+/// The producer calls slot transactions make, so they can be tested without a broker.
+pub trait TransactionalProducer: Send + Sync + 'static {
+    fn init_transactions(&self, timeout: Duration) -> KafkaResult<()>;
+
+    fn begin_transaction(&self) -> KafkaResult<()>;
+
+    /// Hands one record to the producer, without waiting if its queue is full.
+    fn send(&self, record: &OwnedRecord, headers: Option<&RecordHeaders>) -> KafkaResult<()>;
+
+    fn commit_transaction(&self, timeout: Duration) -> KafkaResult<()>;
+
+    fn abort_transaction(&self, timeout: Duration) -> KafkaResult<()>;
+
+    /// Keeps a record that will never be committed, if a dead-letter store is configured.
+    fn dead_letter(&self, record: &Record<'_>);
+}
+
+impl TransactionalProducer for Producer {
+    fn init_transactions(&self, timeout: Duration) -> KafkaResult<()> {
+        rdkafka::producer::Producer::init_transactions(self, timeout)
+    }
+
+    fn begin_transaction(&self) -> KafkaResult<()> {
+        rdkafka::producer::Producer::begin_transaction(self)
+    }
+
+    fn send(&self, record: &OwnedRecord, headers: Option<&RecordHeaders>) -> KafkaResult<()> {
+        let mut kafka_record = BaseRecord::<[u8], _, _>::with_opaque_to(
+            &record.topic,
+            Delivery::transactional(&record.as_record()),
+        )
+        .key(record.key.as_slice())
+        .payload(record.payload.as_slice());
+        kafka_record.partition = record.partition;
+        kafka_record.headers = headers.map(|headers| headers.build(record.tag, Some(record.slot)));
+        ThreadedProducer::send(self, kafka_record).map_err(|(e, _)| e)
+    }
+
+    fn commit_transaction(&self, timeout: Duration) -> KafkaResult<()> {
+        rdkafka::producer::Producer::commit_transaction(self, timeout)
+    }
+
+    fn abort_transaction(&self, timeout: Duration) -> KafkaResult<()> {
+        rdkafka::producer::Producer::abort_transaction(self, timeout)
+    }
+
+    fn dead_letter(&self, record: &Record<'_>) {
+        self.context().dead_letter(record);
+    }
+}
+
+/// Buffers records per slot and publishes each slot in its own Kafka transaction
+/// once it reaches the configured status.
+///
+/// Commits run on their own thread, so a slow or retried commit does not hold up the
+/// publish worker that reported the status. Records that arrive after their slot was
+/// committed, such as later status updates of that slot, are committed together with
+/// the next slot. So are the records of a slot whose transaction could not be
+/// committed. Records of abandoned slots, and late records beyond
+/// `max_late_records`, go to the dead-letter store.
+///
+/// A fatal producer error, such as another instance fencing the transactional id,
+/// fails the sink: every record sent afterwards is rejected with that error.
+pub struct SlotTransactions<P: TransactionalProducer = Producer> {
+    commit_on: SlotStatus,
+    shared: Arc<Shared<P>>,
+    committer: Option<Committer>,
+}
+
+struct Shared<P> {
+    producer: Arc<P>,
+    headers: Option<RecordHeaders>,
+    timeout: Duration,
+    max_late_records: usize,
+    pending: Mutex<PendingSlots>,
+    fatal: OnceLock<KafkaError>,
+}
+
+struct Committer {
+    slots: Sender<u64>,
+    handle: JoinHandle<()>,
+}
+
+#[derive(Default)]
+struct PendingSlots {
+    slots: BTreeMap<u64, Vec<OwnedRecord>>,
+    late: Vec<OwnedRecord>,
+    committed: BTreeSet<u64>,
+}
+
+impl PendingSlots {
+    fn is_late(&self, slot: u64) -> bool {
+        self.committed.contains(&slot)
+            || self
+                .committed
+                .last()
+                .is_some_and(|last| slot < last.saturating_sub(ABANDONED_SLOT_LAG))
+    }
+
+    /// Forgets slots too far behind `slot`, returning their records.
+    fn prune(&mut self, slot: u64) -> Vec<OwnedRecord> {
+        let oldest = slot.saturating_sub(ABANDONED_SLOT_LAG);
+        let kept = self.slots.split_off(&oldest);
+        let dropped = std::mem::replace(&mut self.slots, kept);
+        self.committed = self.committed.split_off(&oldest);
+        dropped.into_values().flatten().collect()
+    }
+
+    /// Removes the oldest late records beyond `max`, returning them.
+    fn trim_late(&mut self, max: usize) -> Vec<OwnedRecord> {
+        let excess = self.late.len().saturating_sub(max);
+        self.late.drain(..excess).collect()
+    }
+}
+
+impl<P: TransactionalProducer> SlotTransactions<P> {
+    pub fn new(
+        producer: Arc<P>,
+        headers: Option<RecordHeaders>,
+        config: &TransactionsConfig,
+    ) -> SinkResult<Self> {
+        let timeout = Duration::from_millis(config.timeout_ms);
+        producer.init_transactions(timeout)?;
+        info!(
+            "Initialized Kafka transactions for id {}",
+            config.transactional_id
+        );
+
+        let shared = Arc::new(Shared {
+            producer,
+            headers,
+            timeout,
+            max_late_records: config.max_late_records,
+            pending: Mutex::new(PendingSlots::default()),
+            fatal: OnceLock::new(),
+        });
+        let (slots, receiver) = mpsc::channel();
+        let handle = {
+            let shared = Arc::clone(&shared);
+            thread::Builder::new()
+                .name("heimdallTxn".to_owned())
+                .spawn(move || shared.run(receiver))
+                .expect("failed to spawn transaction committer")
+        };
+
+        Ok(Self {
+            commit_on: match config.commit_on {
+                CommitStatus::Completed => SlotStatus::Completed,
+                CommitStatus::Confirmed => SlotStatus::Confirmed,
+            },
+            shared,
+            committer: Some(Committer { slots, handle }),
+        })
+    }
+
+    pub fn buffer(&self, record: &Record<'_>) -> SinkResult<()> {
+        self.shared.check()?;
+        let mut pending = self.shared.pending.lock().unwrap();
+        if pending.is_late(record.slot) {
+            pending.late.push(record.into());
+            let trimmed = pending.trim_late(self.shared.max_late_records);
+            drop(pending);
+            self.shared
+                .dead_letter(trimmed, "late records beyond the limit");
+        } else {
+            pending
+                .slots
+                .entry(record.slot)
+                .or_default()
+                .push(record.into());
+        }
+        Ok(())
+    }
+
+    /// Queues the slot's commit if it reached the configured status.
+    pub fn slot_status(&self, slot: u64, status: SlotStatus) -> SinkResult<()> {
+        self.shared.check()?;
+        if status == self.commit_on {
+            if let Some(committer) = &self.committer {
+                committer
+                    .slots
+                    .send(slot)
+                    .map_err(|_| "transaction committer stopped")?;
+            }
+        } else if status == SlotStatus::Dead
+            && let Some(records) = self.shared.pending.lock().unwrap().slots.remove(&slot)
+        {
+            debug!("Discarding {} records of dead slot {slot}", records.len());
+        }
+        Ok(())
+    }
+
+    /// Returns the number of buffered records that were never committed.
+    pub fn uncommitted(&self) -> usize {
+        let pending = self.shared.pending.lock().unwrap();
+        pending.slots.values().map(Vec::len).sum::<usize>() + pending.late.len()
+    }
+
+    /// Waits for the commits queued so far, later statuses no longer commit.
+    pub fn stop(&mut self) {
+        if let Some(Committer { slots, handle }) = self.committer.take() {
+            drop(slots);
+            if handle.join().is_err() {
+                error!("Transaction committer panicked during shutdown");
+            }
+        }
+    }
+}
+
+impl<P: TransactionalProducer> Drop for SlotTransactions<P> {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+impl<P: TransactionalProducer> Shared<P> {
+    /// Fails once the producer hit a fatal error.
+    fn check(&self) -> KafkaResult<()> {
+        match self.fatal.get() {
+            Some(fatal) => Err(fatal.clone()),
+            None => Ok(()),
+        }
+    }
+
+    fn run(&self, slots: Receiver<u64>) {
+        while let Ok(slot) = slots.recv() {
+            if self.fatal.get().is_some() {
+                break;
+            }
+            if let Err(e) = self.commit(slot) {
+                error!("Failed to commit slot {slot}: {e:?}");
+            }
+        }
+    }
+
+    fn commit(&self, slot: u64) -> KafkaResult<()> {
+        let (records, abandoned) = {
+            let mut pending = self.pending.lock().unwrap();
+            let mut records = std::mem::take(&mut pending.late);
+            records.extend(pending.slots.remove(&slot).unwrap_or_default());
+            pending.committed.insert(slot);
+            (records, pending.prune(slot))
+        };
+        self.dead_letter(
+            abandoned,
+            &format!("slots more than {ABANDONED_SLOT_LAG} slots behind {slot}"),
+        );
+        if records.is_empty() {
+            return Ok(());
+        }
+
+        let mut attempt = 1;
+        loop {
+            match self.try_commit(&records) {
+                Ok(()) => {
+                    debug!("Committed {} records of slot {slot}", records.len());
+                    return Ok(());
+                }
+                Err(e) if Self::is_fatal(&e) => {
+                    error!(
+                        "Kafka producer failed fatally committing slot {slot}, the sink stops publishing: {e:?}"
+                    );
+                    let _ = self.fatal.set(e.clone());
+                    let mut pending = self.pending.lock().unwrap();
+                    let mut uncommitted = records;
+                    uncommitted.append(&mut pending.late);
+                    uncommitted.extend(std::mem::take(&mut pending.slots).into_values().flatten());
+                    drop(pending);
+                    self.dead_letter(uncommitted, "a failed producer");
+                    return Err(e);
+                }
+                Err(e) if attempt < COMMIT_ATTEMPTS => {
+                    warn!(
+                        "Failed to commit {} records of slot {slot} (attempt {attempt}), retrying: {e:?}",
+                        records.len()
+                    );
+                    attempt += 1;
+                }
+                Err(e) => {
+                    error!(
+                        "Failed to commit {} records of slot {slot}, keeping them for the next slot: {e:?}",
+                        records.len()
+                    );
+                    let mut pending = self.pending.lock().unwrap();
+                    let late = std::mem::replace(&mut pending.late, records);
+                    pending.late.extend(late);
+                    let trimmed = pending.trim_late(self.max_late_records);
+                    drop(pending);
+                    self.dead_letter(trimmed, "late records beyond the limit");
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    /// Sends the records in a new transaction, aborting it if they cannot be committed.
+    fn try_commit(&self, records: &[OwnedRecord]) -> KafkaResult<()> {
+        self.producer.begin_transaction()?;
+        let sent = records
+            .iter()
+            .try_for_each(|record| self.send(record))
+            .and_then(|()| self.producer.commit_transaction(self.timeout));
+        if let Err(e) = &sent
+            && !Self::is_fatal(e)
+            && let Err(abort_err) = self.producer.abort_transaction(self.timeout)
+        {
+            error!("Failed to abort transaction: {abort_err:?}");
+        }
+        sent
+    }
+
+    fn send(&self, record: &OwnedRecord) -> KafkaResult<()> {
+        loop {
+            match self.producer.send(record, self.headers.as_ref()) {
+                Err(KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull)) => {
+                    thread::sleep(QUEUE_FULL_BACKOFF);
+                }
+                sent => return sent,
+            }
+        }
+    }
+
+    /// Whether the producer cannot be used for transactions anymore.
+    fn is_fatal(error: &KafkaError) -> bool {
+        match error {
+            KafkaError::Transaction(error) => error.is_fatal(),
+            _ => matches!(
+                error.rdkafka_error_code(),
+                Some(
+                    RDKafkaErrorCode::Fatal
+                        | RDKafkaErrorCode::Fenced
+                        | RDKafkaErrorCode::ProducerFenced
+                        | RDKafkaErrorCode::InvalidProducerEpoch
+                        | RDKafkaErrorCode::TransactionalIdAuthorizationFailed
+                )
+            ),
+        }
+    }
+
+    fn dead_letter(&self, records: Vec<OwnedRecord>, reason: &str) {
+        if records.is_empty() {
+            return;
+        }
+        warn!(
+            "Dead-lettering {} uncommitted records of {reason}",
+            records.len()
+        );
+        for record in &records {
+            self.producer.dead_letter(&record.as_record());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{DeliveryTag, EventKind, MessageFormat},
+        std::{collections::VecDeque, time::Instant},
+    };
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    enum Call {
+        Begin,
+        Send(u64, Vec<u8>),
+        Commit,
+        Abort,
+        DeadLetter(u64, Vec<u8>),
+    }
+
+    #[derive(Default)]
+    struct MockProducer {
+        calls: Mutex<Vec<Call>>,
+        /// Returned by the next commits, in order.
+        commit_errors: Mutex<VecDeque<KafkaError>>,
+    }
+
+    impl MockProducer {
+        fn fail_commits(&self, code: RDKafkaErrorCode, times: usize) {
+            let mut errors = self.commit_errors.lock().unwrap();
+            errors.extend((0..times).map(|_| KafkaError::MessageProduction(code)));
+        }
+
+        fn take(&self) -> Vec<Call> {
+            std::mem::take(&mut self.calls.lock().unwrap())
+        }
+
+        /// Waits until `count` commits were attempted.
+        fn wait_for_commits(&self, count: usize) {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while self.count(&Call::Commit) < count {
+                assert!(Instant::now() < deadline, "timed out waiting for commits");
+                thread::sleep(Duration::from_millis(1));
+            }
+        }
+
+        fn count(&self, call: &Call) -> usize {
+            let calls = self.calls.lock().unwrap();
+            calls.iter().filter(|made| *made == call).count()
+        }
+    }
+
+    impl TransactionalProducer for MockProducer {
+        fn init_transactions(&self, _timeout: Duration) -> KafkaResult<()> {
+            Ok(())
+        }
+
+        fn begin_transaction(&self) -> KafkaResult<()> {
+            self.calls.lock().unwrap().push(Call::Begin);
+            Ok(())
+        }
+
+        fn send(&self, record: &OwnedRecord, _headers: Option<&RecordHeaders>) -> KafkaResult<()> {
+            let call = Call::Send(record.slot, record.payload.clone());
+            self.calls.lock().unwrap().push(call);
+            Ok(())
+        }
+
+        fn commit_transaction(&self, _timeout: Duration) -> KafkaResult<()> {
+            self.calls.lock().unwrap().push(Call::Commit);
+            match self.commit_errors.lock().unwrap().pop_front() {
+                Some(error) => Err(error),
+                None => Ok(()),
+            }
+        }
+
+        fn abort_transaction(&self, _timeout: Duration) -> KafkaResult<()> {
+            self.calls.lock().unwrap().push(Call::Abort);
+            Ok(())
+        }
+
+        fn dead_letter(&self, record: &Record<'_>) {
+            let call = Call::DeadLetter(record.slot, record.payload.to_vec());
+            self.calls.lock().unwrap().push(call);
+        }
+    }
+
+    fn transactions(
+        max_late_records: usize,
+    ) -> (Arc<MockProducer>, SlotTransactions<MockProducer>) {
+        let producer = Arc::new(MockProducer::default());
+        let config = TransactionsConfig {
+            transactional_id: "heimdall-test".to_owned(),
+            commit_on: CommitStatus::Confirmed,
+            timeout_ms: 1_000,
+            max_late_records,
+        };
+        let transactions = SlotTransactions::new(Arc::clone(&producer), None, &config).unwrap();
+        (producer, transactions)
+    }
+
+    fn record(slot: u64, payload: &[u8]) -> Record<'_> {
+        Record {
+            topic: "slots",
+            tag: DeliveryTag::new(EventKind::Slot, MessageFormat::Protobuf),
+            slot,
+            key: b"key",
+            partition: None,
+            payload,
+            event: None,
+        }
+    }
+
+    fn send(slot: u64, payload: u8) -> Call {
+        Call::Send(slot, vec![payload])
+    }
+
+    #[test]
+    fn commits_each_slot_at_the_configured_status() {
+        let (producer, mut transactions) = transactions(100);
+        transactions.buffer(&record(10, &[1])).unwrap();
+        transactions.buffer(&record(11, &[2])).unwrap();
+        transactions.buffer(&record(10, &[3])).unwrap();
+        transactions.slot_status(10, SlotStatus::Processed).unwrap();
+        transactions.slot_status(10, SlotStatus::Confirmed).unwrap();
+        transactions.stop();
+
+        assert_eq!(
+            producer.take(),
+            [Call::Begin, send(10, 1), send(10, 3), Call::Commit]
+        );
+        assert_eq!(transactions.uncommitted(), 1);
+    }
+
+    #[test]
+    fn discards_dead_slots() {
+        let (producer, mut transactions) = transactions(100);
+        transactions.buffer(&record(10, &[1])).unwrap();
+        transactions.slot_status(10, SlotStatus::Dead).unwrap();
+        transactions.slot_status(10, SlotStatus::Confirmed).unwrap();
+        transactions.stop();
+
+        assert!(producer.take().is_empty());
+        assert_eq!(transactions.uncommitted(), 0);
+    }
+
+    #[test]
+    fn retries_failed_commits() {
+        let (producer, mut transactions) = transactions(100);
+        producer.fail_commits(RDKafkaErrorCode::RequestTimedOut, 1);
+        transactions.buffer(&record(10, &[1])).unwrap();
+        transactions.slot_status(10, SlotStatus::Confirmed).unwrap();
+        transactions.stop();
+
+        assert_eq!(
+            producer.take(),
+            [
+                Call::Begin,
+                send(10, 1),
+                Call::Commit,
+                Call::Abort,
+                Call::Begin,
+                send(10, 1),
+                Call::Commit
+            ]
+        );
+        assert_eq!(transactions.uncommitted(), 0);
+    }
+
+    #[test]
+    fn keeps_records_of_failed_commits_for_the_next_slot() {
+        let (producer, mut transactions) = transactions(100);
+        producer.fail_commits(RDKafkaErrorCode::RequestTimedOut, COMMIT_ATTEMPTS);
+        transactions.buffer(&record(10, &[1])).unwrap();
+        transactions.slot_status(10, SlotStatus::Confirmed).unwrap();
+        producer.wait_for_commits(COMMIT_ATTEMPTS);
+        transactions.buffer(&record(11, &[2])).unwrap();
+        transactions.slot_status(11, SlotStatus::Confirmed).unwrap();
+        transactions.stop();
+
+        let calls = producer.take();
+        assert_eq!(
+            calls[calls.len() - 4..],
+            [Call::Begin, send(10, 1), send(11, 2), Call::Commit]
+        );
+        assert_eq!(transactions.uncommitted(), 0);
+    }
+
+    #[test]
+    fn commits_late_records_with_the_next_slot() {
+        let (producer, mut transactions) = transactions(100);
+        transactions.buffer(&record(10, &[1])).unwrap();
+        transactions.slot_status(10, SlotStatus::Confirmed).unwrap();
+        producer.wait_for_commits(1);
+        transactions.buffer(&record(10, &[2])).unwrap();
+        transactions.buffer(&record(11, &[3])).unwrap();
+        transactions.slot_status(11, SlotStatus::Confirmed).unwrap();
+        transactions.stop();
+
+        assert_eq!(
+            producer.take(),
+            [
+                Call::Begin,
+                send(10, 1),
+                Call::Commit,
+                Call::Begin,
+                send(10, 2),
+                send(11, 3),
+                Call::Commit
+            ]
+        );
+    }
+
+    #[test]
+    fn dead_letters_late_records_beyond_the_limit() {
+        let (producer, mut transactions) = transactions(2);
+        transactions.buffer(&record(10, &[1])).unwrap();
+        transactions.slot_status(10, SlotStatus::Confirmed).unwrap();
+        producer.wait_for_commits(1);
+        producer.take();
+        for payload in 2..5 {
+            transactions.buffer(&record(10, &[payload])).unwrap();
+        }
+        transactions.stop();
+
+        assert_eq!(producer.take(), [Call::DeadLetter(10, vec![2])]);
+        assert_eq!(transactions.uncommitted(), 2);
+    }
+
+    #[test]
+    fn dead_letters_abandoned_slots() {
+        let (producer, mut transactions) = transactions(100);
+        transactions.buffer(&record(10, &[1])).unwrap();
+        transactions.buffer(&record(200, &[2])).unwrap();
+        transactions
+            .slot_status(200, SlotStatus::Confirmed)
+            .unwrap();
+        transactions.stop();
+
+        assert_eq!(
+            producer.take(),
+            [
+                Call::DeadLetter(10, vec![1]),
+                Call::Begin,
+                send(200, 2),
+                Call::Commit
+            ]
+        );
+        assert_eq!(transactions.uncommitted(), 0);
+    }
+
+    #[test]
+    fn fatal_errors_fail_the_sink() {
+        let (producer, mut transactions) = transactions(100);
+        producer.fail_commits(RDKafkaErrorCode::ProducerFenced, 1);
+        transactions.buffer(&record(10, &[1])).unwrap();
+        transactions.buffer(&record(11, &[2])).unwrap();
+        transactions.slot_status(10, SlotStatus::Confirmed).unwrap();
+        producer.wait_for_commits(1);
+        transactions.stop();
+
+        assert_eq!(
+            producer.take(),
+            [
+                Call::Begin,
+                send(10, 1),
+                Call::Commit,
+                Call::DeadLetter(10, vec![1]),
+                Call::DeadLetter(11, vec![2])
+            ]
+        );
+        assert!(transactions.buffer(&record(12, &[3])).is_err());
+        assert!(transactions.slot_status(12, SlotStatus::Confirmed).is_err());
+        assert_eq!(transactions.uncommitted(), 0);
+    }
+}
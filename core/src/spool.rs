@@ -0,0 +1,599 @@
+use {
+    crate::{DeliveryTag, FsyncPolicy, Record, SpoolConfig},
+    log::{debug, info, warn},
+    std::{
+        collections::VecDeque,
+        fs::{self, File, OpenOptions},
+        io::{self, BufReader, ErrorKind, Read, Write},
+        path::PathBuf,
+        sync::{
+            Mutex,
+            atomic::{AtomicBool, AtomicU64, Ordering},
+        },
+    },
+};
+
+const SEGMENT_EXTENSION: &str = "seg";
+/// Stored for records without an explicit partition, like librdkafka's `RD_KAFKA_PARTITION_UA`.
+const UNASSIGNED_PARTITION: i32 = -1;
+
+/// A single record read back from a spool segment.
+#[derive(Debug)]
+pub struct SpooledRecord {
+    pub tag: DeliveryTag,
+    pub slot: u64,
+    /// Partition the record must go to, left to the partitioner if None.
+    pub partition: Option<i32>,
+    pub topic: String,
+    pub key: Vec<u8>,
+    pub payload: Vec<u8>,
+}
+
+#[derive(Debug, Default)]
+pub struct SpoolStats {
+    pub spooled: AtomicU64,
+    pub replayed: AtomicU64,
+    pub dropped: AtomicU64,
+}
+
+struct ActiveSegment {
+    id: u64,
+    file: File,
+    bytes: u64,
+}
+
+struct SpoolState {
+    active: Option<ActiveSegment>,
+    sealed: VecDeque<u64>,
+    next_id: u64,
+    total_bytes: u64,
+}
+
+/// Local write-ahead spool for records that could not be handed to Kafka.
+///
+/// Records are appended to numbered segment files. Once a segment reaches
+/// `segment_bytes` it is sealed, and sealed segments are replayed and deleted
+/// oldest first, so records leave the spool in the order they entered it.
+///
+/// When the drainer takes the last segment it hands the spool back: the active
+/// segment is sealed and newer records go to Kafka directly again, so they can
+/// overtake the records of that final segment while it is replayed.
+pub struct Spool {
+    dir: PathBuf,
+    segment_bytes: u64,
+    max_bytes: u64,
+    fsync: FsyncPolicy,
+    state: Mutex<SpoolState>,
+    pending: AtomicBool,
+    stats: SpoolStats,
+}
+
+impl Spool {
+    pub fn open(config: &SpoolConfig) -> io::Result<Self> {
+        fs::create_dir_all(&config.path)?;
+
+        let mut segments = Vec::new();
+        let mut total_bytes = 0;
+        for entry in fs::read_dir(&config.path)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(SEGMENT_EXTENSION) {
+                continue;
+            }
+            if let Some(id) = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.parse::<u64>().ok())
+            {
+                total_bytes += fs::metadata(&path)?.len();
+                segments.push(id);
+            }
+        }
+        segments.sort_unstable();
+
+        let next_id = segments.last().map_or(0, |id| id + 1);
+        if !segments.is_empty() {
+            info!(
+                "Found {} spool segments ({} bytes) in {:?}",
+                segments.len(),
+                total_bytes,
+                config.path
+            );
+        }
+
+        Ok(Self {
+            dir: config.path.clone(),
+            segment_bytes: config.segment_bytes,
+            max_bytes: config.max_bytes,
+            fsync: config.fsync,
+            pending: AtomicBool::new(!segments.is_empty()),
+            state: Mutex::new(SpoolState {
+                active: None,
+                sealed: segments.into(),
+                next_id,
+                total_bytes,
+            }),
+            stats: SpoolStats::default(),
+        })
+    }
+
+    /// Returns true while newer records have to be appended behind spooled ones.
+    pub fn is_pending(&self) -> bool {
+        self.pending.load(Ordering::Acquire)
+    }
+
+    /// Returns true if no segment is waiting to be replayed.
+    pub fn is_empty(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        state.sealed.is_empty() && state.active.is_none()
+    }
+
+    pub fn stats(&self) -> &SpoolStats {
+        &self.stats
+    }
+
+    /// Appends a record as its tag byte, slot and partition (-1 if unset), then the
+    /// topic, key and payload, each prefixed with its length. Integers are little-endian.
+    ///
+    /// The record is written with a single call, and a failed write is cut off again,
+    /// so a segment never holds part of a record followed by more records.
+    pub fn append(&self, record: &Record<'_>) -> io::Result<()> {
+        let buf = Self::encode(record)?;
+        let record_len = buf.len() as u64;
+
+        let mut state = self.state.lock().unwrap();
+        if state.total_bytes + record_len > self.max_bytes {
+            self.stats.dropped.fetch_add(1, Ordering::Relaxed);
+            return Err(io::Error::other("spool size limit reached"));
+        }
+
+        if state
+            .active
+            .as_ref()
+            .is_some_and(|active| active.bytes + record_len > self.segment_bytes)
+        {
+            self.seal_active(&mut state)?;
+        }
+
+        if state.active.is_none() {
+            let id = state.next_id;
+            state.next_id += 1;
+            let file = OpenOptions::new()
+                .create_new(true)
+                .append(true)
+                .open(self.segment_path(id))?;
+            state.active = Some(ActiveSegment { id, file, bytes: 0 });
+        }
+
+        let active = state.active.as_mut().expect("active segment");
+        let written = active.file.write_all(&buf).and_then(|()| {
+            if self.fsync == FsyncPolicy::Always {
+                active.file.sync_data()?;
+            }
+            Ok(())
+        });
+        if let Err(e) = written {
+            if let Err(truncate_err) = active.file.set_len(active.bytes) {
+                // Readers stop at a torn final record, so nothing may follow it.
+                warn!(
+                    "Failed to truncate spool segment {} after a failed write, sealing it: {:?}",
+                    active.id, truncate_err
+                );
+                self.seal_active(&mut state)?;
+            }
+            return Err(e);
+        }
+        active.bytes += record_len;
+        state.total_bytes += record_len;
+
+        self.pending.store(true, Ordering::Release);
+        self.stats.spooled.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn encode(record: &Record<'_>) -> io::Result<Vec<u8>> {
+        let Record {
+            topic,
+            tag,
+            slot,
+            key,
+            partition,
+            payload,
+            ..
+        } = *record;
+        let too_long = |what: &str| {
+            io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("{what} of topic {topic} is too long to spool"),
+            )
+        };
+        let topic_len = u16::try_from(topic.len()).map_err(|_| too_long("name"))?;
+        let key_len = u32::try_from(key.len()).map_err(|_| too_long("record key"))?;
+        let payload_len = u32::try_from(payload.len()).map_err(|_| too_long("record payload"))?;
+
+        let mut buf =
+            Vec::with_capacity(1 + 8 + 4 + 2 + topic.len() + 4 + key.len() + 4 + payload.len());
+        buf.push(tag.to_byte());
+        buf.extend_from_slice(&slot.to_le_bytes());
+        buf.extend_from_slice(&partition.unwrap_or(UNASSIGNED_PARTITION).to_le_bytes());
+        buf.extend_from_slice(&topic_len.to_le_bytes());
+        buf.extend_from_slice(topic.as_bytes());
+        buf.extend_from_slice(&key_len.to_le_bytes());
+        buf.extend_from_slice(key);
+        buf.extend_from_slice(&payload_len.to_le_bytes());
+        buf.extend_from_slice(payload);
+        Ok(buf)
+    }
+
+    /// Returns the oldest sealed segment, sealing the active one if nothing else is left.
+    ///
+    /// Handing out the last segment clears the pending flag, otherwise records that
+    /// keep arriving during the replay would hold the spool open forever.
+    pub fn next_segment(&self) -> io::Result<Option<u64>> {
+        let mut state = self.state.lock().unwrap();
+        if state.sealed.is_empty() && state.active.is_some() {
+            self.seal_active(&mut state)?;
+        }
+        if state.sealed.len() <= 1 && state.active.is_none() {
+            self.pending.store(false, Ordering::Release);
+        }
+        Ok(state.sealed.front().copied())
+    }
+
+    pub fn read_segment(&self, id: u64) -> io::Result<SegmentReader> {
+        let file = File::open(self.segment_path(id))?;
+        Ok(SegmentReader {
+            id,
+            reader: BufReader::new(file),
+        })
+    }
+
+    /// Deletes a fully replayed segment.
+    pub fn remove_segment(&self, id: u64) -> io::Result<()> {
+        let path = self.segment_path(id);
+        let len = fs::metadata(&path)?.len();
+        fs::remove_file(&path)?;
+
+        let mut state = self.state.lock().unwrap();
+        state.sealed.retain(|sealed| *sealed != id);
+        state.total_bytes = state.total_bytes.saturating_sub(len);
+        debug!("Removed drained spool segment {id}");
+        Ok(())
+    }
+
+    fn seal_active(&self, state: &mut SpoolState) -> io::Result<()> {
+        if let Some(active) = state.active.take() {
+            state.sealed.push_back(active.id);
+            if self.fsync != FsyncPolicy::Never {
+                active.file.sync_all()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn segment_path(&self, id: u64) -> PathBuf {
+        self.dir.join(format!("{id:020}.{SEGMENT_EXTENSION}"))
+    }
+}
+
+impl Drop for Spool {
+    fn drop(&mut self) {
+        let mut state = self.state.lock().unwrap();
+        if let Err(e) = self.seal_active(&mut state) {
+            warn!("Failed to seal spool segment on shutdown: {e:?}");
+        }
+    }
+}
+
+pub struct SegmentReader {
+    id: u64,
+    reader: BufReader<File>,
+}
+
+impl SegmentReader {
+    fn read_record(&mut self) -> io::Result<Option<SpooledRecord>> {
+        let mut tag = [0; 1];
+        match self.reader.read_exact(&mut tag) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        }
+
+        match self.read_body(tag[0]) {
+            Ok(record) => Ok(Some(record)),
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
+                warn!("Spool segment {} ends with a truncated record", self.id);
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn read_body(&mut self, tag: u8) -> io::Result<SpooledRecord> {
+        let tag = DeliveryTag::from_byte(tag).ok_or_else(|| {
+            io::Error::new(ErrorKind::InvalidData, format!("unknown record tag {tag}"))
+        })?;
+        let mut slot = [0; 8];
+        self.reader.read_exact(&mut slot)?;
+        let slot = u64::from_le_bytes(slot);
+        let mut partition = [0; 4];
+        self.reader.read_exact(&mut partition)?;
+        let partition = Some(i32::from_le_bytes(partition)).filter(|p| *p != UNASSIGNED_PARTITION);
+        let mut topic_len = [0; 2];
+        self.reader.read_exact(&mut topic_len)?;
+        let topic = String::from_utf8(self.read_chunk(u16::from_le_bytes(topic_len) as usize)?)
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+        let key_len = self.read_len()?;
+        let key = self.read_chunk(key_len)?;
+        let payload_len = self.read_len()?;
+        let payload = self.read_chunk(payload_len)?;
+        Ok(SpooledRecord {
+            tag,
+            slot,
+            partition,
+            topic,
+            key,
+            payload,
+        })
+    }
+
+    fn read_len(&mut self) -> io::Result<usize> {
+        let mut len = [0; 4];
+        self.reader.read_exact(&mut len)?;
+        Ok(u32::from_le_bytes(len) as usize)
+    }
+
+    fn read_chunk(&mut self, len: usize) -> io::Result<Vec<u8>> {
+        let mut buf = vec![0; len];
+        self.reader.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+}
+
+impl Iterator for SegmentReader {
+    type Item = io::Result<SpooledRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{EventKind, MessageFormat},
+        std::path::Path,
+    };
+
+    /// A spool directory that is removed when the test ends.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path =
+                std::env::temp_dir().join(format!("heimdall-spool-{name}-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            Self(path)
+        }
+
+        fn config(&self, segment_bytes: u64, max_bytes: u64) -> SpoolConfig {
+            SpoolConfig {
+                path: self.0.clone(),
+                segment_bytes,
+                max_bytes,
+                fsync: FsyncPolicy::Never,
+                drain_interval_ms: 0,
+            }
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn record<'a>(topic: &'a str, partition: Option<i32>, payload: &'a [u8]) -> Record<'a> {
+        Record {
+            topic,
+            tag: DeliveryTag::new(EventKind::Transaction, MessageFormat::ConfluentProtobuf),
+            slot: 12_345,
+            key: b"key",
+            partition,
+            payload,
+            event: None,
+        }
+    }
+
+    fn read_all(spool: &Spool, id: u64) -> Vec<SpooledRecord> {
+        spool
+            .read_segment(id)
+            .unwrap()
+            .collect::<io::Result<_>>()
+            .unwrap()
+    }
+
+    fn segments(dir: &Path) -> usize {
+        fs::read_dir(dir).unwrap().count()
+    }
+
+    #[test]
+    fn records_round_trip() {
+        let dir = TempDir::new("round-trip");
+        let spool = Spool::open(&dir.config(1024, 4096)).unwrap();
+        assert!(!spool.is_pending());
+        spool.append(&record("accounts", None, &[1, 2, 3])).unwrap();
+        spool.append(&record("slots", Some(7), &[])).unwrap();
+        assert!(spool.is_pending());
+
+        let id = spool.next_segment().unwrap().unwrap();
+        let records = read_all(&spool, id);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].tag, record("", None, &[]).tag);
+        assert_eq!(records[0].slot, 12_345);
+        assert_eq!(records[0].partition, None);
+        assert_eq!(records[0].topic, "accounts");
+        assert_eq!(records[0].key, b"key");
+        assert_eq!(records[0].payload, [1, 2, 3]);
+        assert_eq!(records[1].partition, Some(7));
+        assert_eq!(records[1].topic, "slots");
+        assert!(records[1].payload.is_empty());
+
+        spool.remove_segment(id).unwrap();
+        assert!(!spool.is_pending());
+        assert_eq!(spool.next_segment().unwrap(), None);
+    }
+
+    #[test]
+    fn truncated_final_record_ends_the_segment() {
+        let dir = TempDir::new("truncated");
+        let config = dir.config(1024, 4096);
+        {
+            let spool = Spool::open(&config).unwrap();
+            spool.append(&record("accounts", None, &[1; 16])).unwrap();
+            spool.append(&record("accounts", None, &[2; 16])).unwrap();
+        }
+        let path = fs::read_dir(&dir.0)
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap()
+            .path();
+        let len = fs::metadata(&path).unwrap().len();
+        OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(len - 5)
+            .unwrap();
+
+        let spool = Spool::open(&config).unwrap();
+        assert!(spool.is_pending());
+        let id = spool.next_segment().unwrap().unwrap();
+        let records = read_all(&spool, id);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].payload, [1; 16]);
+    }
+
+    #[test]
+    fn unknown_tag_is_an_error() {
+        let dir = TempDir::new("unknown-tag");
+        let config = dir.config(1024, 4096);
+        fs::create_dir_all(&dir.0).unwrap();
+        fs::write(
+            dir.0.join(format!("{:020}.{SEGMENT_EXTENSION}", 0)),
+            [0xff; 32],
+        )
+        .unwrap();
+
+        let spool = Spool::open(&config).unwrap();
+        let error = spool.read_segment(0).unwrap().next().unwrap().unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn segments_rotate_and_survive_restart() {
+        let dir = TempDir::new("rotate");
+        let config = dir.config(64, 4096);
+        {
+            let spool = Spool::open(&config).unwrap();
+            for payload in 0..4u8 {
+                spool
+                    .append(&record("accounts", None, &[payload; 32]))
+                    .unwrap();
+            }
+        }
+        assert_eq!(segments(&dir.0), 4);
+
+        let spool = Spool::open(&config).unwrap();
+        for payload in 0..4u8 {
+            let id = spool.next_segment().unwrap().unwrap();
+            let records = read_all(&spool, id);
+            assert_eq!(records.len(), 1);
+            assert_eq!(records[0].payload, [payload; 32]);
+            spool.remove_segment(id).unwrap();
+        }
+        assert!(!spool.is_pending());
+        assert_eq!(segments(&dir.0), 0);
+    }
+
+    #[test]
+    fn taking_the_last_segment_hands_the_spool_back() {
+        let dir = TempDir::new("hand-back");
+        let spool = Spool::open(&dir.config(1024, 4096)).unwrap();
+        spool.append(&record("accounts", None, &[1])).unwrap();
+
+        let first = spool.next_segment().unwrap().unwrap();
+        assert!(!spool.is_pending());
+        assert!(!spool.is_empty());
+
+        // A record spooled during the replay starts a new segment.
+        spool.append(&record("accounts", None, &[2])).unwrap();
+        assert!(spool.is_pending());
+        spool.remove_segment(first).unwrap();
+
+        let second = spool.next_segment().unwrap().unwrap();
+        assert_ne!(first, second);
+        assert_eq!(read_all(&spool, second)[0].payload, [2]);
+        spool.remove_segment(second).unwrap();
+        assert!(!spool.is_pending());
+        assert!(spool.is_empty());
+    }
+
+    #[test]
+    fn drains_while_records_are_appended() {
+        const RECORDS: u32 = 2_000;
+        let dir = TempDir::new("concurrent");
+        let spool = Spool::open(&dir.config(256, 1 << 20)).unwrap();
+
+        let payloads = std::thread::scope(|scope| {
+            let writer = scope.spawn(|| {
+                for n in 0..RECORDS {
+                    spool
+                        .append(&record("accounts", None, &n.to_le_bytes()))
+                        .unwrap();
+                }
+            });
+
+            let mut payloads = Vec::new();
+            while !writer.is_finished() || !spool.is_empty() {
+                let Some(id) = spool.next_segment().unwrap() else {
+                    std::thread::yield_now();
+                    continue;
+                };
+                payloads.extend(
+                    read_all(&spool, id)
+                        .into_iter()
+                        .map(|record| u32::from_le_bytes(record.payload.try_into().unwrap())),
+                );
+                spool.remove_segment(id).unwrap();
+            }
+            payloads
+        });
+
+        assert_eq!(payloads, (0..RECORDS).collect::<Vec<_>>());
+        assert!(!spool.is_pending());
+        assert_eq!(segments(&dir.0), 0);
+    }
+
+    #[test]
+    fn rejects_topics_too_long_to_spool() {
+        let dir = TempDir::new("long-topic");
+        let spool = Spool::open(&dir.config(1 << 20, 1 << 20)).unwrap();
+        let topic = "t".repeat(usize::from(u16::MAX) + 1);
+        let error = spool.append(&record(&topic, None, &[1])).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        assert!(!spool.is_pending());
+        assert!(spool.is_empty());
+    }
+
+    #[test]
+    fn drops_records_beyond_max_bytes() {
+        let dir = TempDir::new("max-bytes");
+        let spool = Spool::open(&dir.config(1024, 100)).unwrap();
+        spool.append(&record("accounts", None, &[0; 40])).unwrap();
+        assert!(spool.append(&record("accounts", None, &[0; 40])).is_err());
+        assert_eq!(spool.stats().spooled.load(Ordering::Relaxed), 1);
+        assert_eq!(spool.stats().dropped.load(Ordering::Relaxed), 1);
+    }
+}
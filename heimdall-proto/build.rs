@@ -0,0 +1,84 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut config = prost_build::Config::new();
+    config
+        .type_attribute(
+            "MessageWrapper.event_message",
+            "#[allow(clippy::large_enum_variant)]",
+        )
+        // JSON output renders keys, hashes and signatures as base58, raw data as
+        // base64 and enums by name.
+        .type_attribute(".", "#[derive(serde::Serialize)]")
+        .type_attribute(
+            "MessageWrapper.event_message",
+            "#[serde(rename_all = \"snake_case\")]",
+        )
+        .type_attribute(
+            "SanitizedMessage.message_payload",
+            "#[serde(rename_all = \"snake_case\")]",
+        );
+    for field in [
+        "UpdateAccountEvent.pubkey",
+        "UpdateAccountEvent.owner",
+        "MessageAddressTableLookup.account_key",
+        "V0Message.recent_block_hash",
+        "LegacyMessage.recent_block_hash",
+        "SanitizedTransaction.message_hash",
+        "TransactionEvent.signature",
+        "EntryEvent.hash",
+        "TransactionReturnData.program_id",
+        "AccountMeta.pubkey",
+    ] {
+        config.field_attribute(field, "#[serde(serialize_with = \"crate::json::base58\")]");
+    }
+    for field in [
+        "LoadedAddresses.writable",
+        "LoadedAddresses.readonly",
+        "V0Message.account_keys",
+        "LegacyMessage.account_keys",
+        "SanitizedTransaction.signatures",
+    ] {
+        config.field_attribute(
+            field,
+            "#[serde(serialize_with = \"crate::json::base58_list\")]",
+        );
+    }
+    config
+        .field_attribute(
+            "UpdateAccountEvent.txn_signature",
+            "#[serde(serialize_with = \"crate::json::base58_option\")]",
+        )
+        .field_attribute(
+            "UpdateAccountEvent.data",
+            "#[serde(serialize_with = \"crate::json::base64\")]",
+        )
+        .field_attribute(
+            "TransactionReturnData.data",
+            "#[serde(serialize_with = \"crate::json::base64\")]",
+        )
+        .field_attribute(
+            "CompiledInstruction.data",
+            "#[serde(serialize_with = \"crate::json::base64\")]",
+        )
+        .field_attribute(
+            "SlotStatusEvent.status",
+            "#[serde(serialize_with = \"crate::json::slot_status\")]",
+        )
+        .field_attribute(
+            "AccountMeta.source",
+            "#[serde(serialize_with = \"crate::json::account_source\")]",
+        )
+        .field_attribute(
+            "TransactionError.kind",
+            "#[serde(serialize_with = \"crate::json::transaction_error_kind\")]",
+        )
+        .field_attribute(
+            "InstructionError.kind",
+            "#[serde(serialize_with = \"crate::json::instruction_error_kind\")]",
+        )
+        .field_attribute(
+            "Reward.reward_type",
+            "#[serde(serialize_with = \"crate::json::reward_type\")]",
+        )
+        .compile_protos(&["proto/heimdall.proto"], &["proto/"])?;
+    Ok(())
+}
@@ -0,0 +1,251 @@
+use crate::{
+    SanitizedTransaction, SlotStatus, SlotStatusEvent, TransactionEvent, TransactionStatusMeta,
+    UpdateAccountEvent, message_wrapper, sanitized_message,
+};
+#[cfg(feature = "plugin")]
+use agave_geyser_plugin_interface::geyser_plugin_interface::SlotStatus as PluginSlotStatus;
+
+/// An account referenced by a transaction, with its signer and writable flags.
+#[derive(Debug, Clone, Copy)]
+pub struct TransactionAccount<'a> {
+    pub key: &'a [u8],
+    pub is_signer: bool,
+    pub is_writable: bool,
+}
+
+/// An instruction executed by a transaction, top-level or inner.
+#[derive(Debug, Clone, Copy)]
+pub struct InvokedInstruction<'a> {
+    pub program: &'a [u8],
+    pub data: &'a [u8],
+    /// Whether the instruction was invoked through CPI.
+    pub inner: bool,
+}
+
+#[cfg(feature = "plugin")]
+impl From<PluginSlotStatus> for SlotStatus {
+    fn from(other: PluginSlotStatus) -> Self {
+        match other {
+            PluginSlotStatus::Processed => SlotStatus::Processed,
+            PluginSlotStatus::Rooted => SlotStatus::Rooted,
+            PluginSlotStatus::Confirmed => SlotStatus::Confirmed,
+            PluginSlotStatus::FirstShredReceived => SlotStatus::FirstShredReceived,
+            PluginSlotStatus::Completed => SlotStatus::Completed,
+            PluginSlotStatus::CreatedBank => SlotStatus::CreatedBank,
+            PluginSlotStatus::Dead(_) => SlotStatus::Dead,
+        }
+    }
+}
+
+/// Version of `heimdall.proto`, bumped whenever a change breaks existing readers.
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EventKind {
+    Account,
+    Slot,
+    Transaction,
+    BlockMetadata,
+    Entry,
+}
+
+impl EventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventKind::Account => "account",
+            EventKind::Slot => "slot",
+            EventKind::Transaction => "transaction",
+            EventKind::BlockMetadata => "block_metadata",
+            EventKind::Entry => "entry",
+        }
+    }
+}
+
+impl message_wrapper::EventMessage {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            message_wrapper::EventMessage::Account(_) => EventKind::Account,
+            message_wrapper::EventMessage::Slot(_) => EventKind::Slot,
+            message_wrapper::EventMessage::Transaction(_) => EventKind::Transaction,
+            message_wrapper::EventMessage::BlockMetadata(_) => EventKind::BlockMetadata,
+            message_wrapper::EventMessage::Entry(_) => EventKind::Entry,
+        }
+    }
+
+    pub fn slot(&self) -> u64 {
+        match self {
+            message_wrapper::EventMessage::Account(ev) => ev.slot,
+            message_wrapper::EventMessage::Slot(ev) => ev.slot,
+            message_wrapper::EventMessage::Transaction(ev) => ev.slot,
+            message_wrapper::EventMessage::BlockMetadata(ev) => ev.slot,
+            message_wrapper::EventMessage::Entry(ev) => ev.slot,
+        }
+    }
+}
+
+impl UpdateAccountEvent {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        slot: u64,
+        pubkey: Vec<u8>,
+        lamports: u64,
+        owner: Vec<u8>,
+        executable: bool,
+        rent_epoch: u64,
+        data: Vec<u8>,
+        write_version: u64,
+        txn_signature: Option<Vec<u8>>,
+    ) -> Self {
+        Self {
+            slot,
+            pubkey,
+            lamports,
+            owner,
+            executable,
+            rent_epoch,
+            data,
+            write_version,
+            txn_signature,
+            data_slices: Vec::new(),
+            data_len: None,
+        }
+    }
+}
+
+impl SlotStatusEvent {
+    pub fn new(slot: u64, parent: u64, status: SlotStatus) -> Self {
+        Self {
+            slot,
+            parent,
+            status: status.into(),
+        }
+    }
+}
+
+impl TransactionEvent {
+    pub fn new(
+        signature: Vec<u8>,
+        is_vote: bool,
+        transaction: Option<SanitizedTransaction>,
+        transaction_status_meta: Option<TransactionStatusMeta>,
+        slot: u64,
+        index: Option<u64>,
+    ) -> Self {
+        Self {
+            signature,
+            is_vote,
+            transaction,
+            transaction_status_meta,
+            slot,
+            index,
+        }
+    }
+
+    pub fn is_failed(&self) -> bool {
+        self.transaction_status_meta
+            .as_ref()
+            .is_some_and(|meta| meta.is_status_err)
+    }
+
+    /// Returns the static account keys followed by any addresses loaded from lookup tables.
+    pub fn account_keys(&self) -> Vec<&[u8]> {
+        let Some(message) = self
+            .transaction
+            .as_ref()
+            .and_then(|tx| tx.message.as_ref())
+            .and_then(|message| message.message_payload.as_ref())
+        else {
+            return Vec::new();
+        };
+
+        match message {
+            sanitized_message::MessagePayload::Legacy(legacy) => legacy
+                .message
+                .iter()
+                .flat_map(|message| message.account_keys.iter())
+                .map(Vec::as_slice)
+                .collect(),
+            sanitized_message::MessagePayload::V0(v0) => {
+                let loaded = v0
+                    .loaded_adresses
+                    .iter()
+                    .flat_map(|loaded| loaded.writable.iter().chain(loaded.readonly.iter()));
+                v0.message
+                    .iter()
+                    .flat_map(|message| message.account_keys.iter())
+                    .chain(loaded)
+                    .map(Vec::as_slice)
+                    .collect()
+            }
+        }
+    }
+
+    /// Returns every account the transaction references with its signer and writable
+    /// flags, in the same order as `account_keys`.
+    pub fn accounts(&self) -> Vec<TransactionAccount<'_>> {
+        self.transaction
+            .iter()
+            .flat_map(|tx| tx.accounts.iter())
+            .map(|account| TransactionAccount {
+                key: &account.pubkey,
+                is_signer: account.is_signer,
+                is_writable: account.is_writable,
+            })
+            .collect()
+    }
+
+    /// Returns the top-level instructions followed by the inner instructions recorded
+    /// in the status meta. Instructions whose program index is out of range are skipped.
+    pub fn instructions(&self) -> Vec<InvokedInstruction<'_>> {
+        let keys = self.account_keys();
+        let top_level = self
+            .transaction
+            .as_ref()
+            .and_then(|tx| tx.message.as_ref())
+            .and_then(|message| message.message_payload.as_ref())
+            .map(|message| match message {
+                sanitized_message::MessagePayload::Legacy(legacy) => legacy
+                    .message
+                    .as_ref()
+                    .map_or(&[][..], |m| m.instructions.as_slice()),
+                sanitized_message::MessagePayload::V0(v0) => v0
+                    .message
+                    .as_ref()
+                    .map_or(&[][..], |m| m.instructions.as_slice()),
+            })
+            .unwrap_or_default();
+        let inner = self
+            .transaction_status_meta
+            .iter()
+            .flat_map(|meta| meta.inner_instructions.iter())
+            .flat_map(|inner| inner.instructions.iter())
+            .filter_map(|inner| inner.instruction.as_ref());
+
+        top_level
+            .iter()
+            .map(|ix| (ix, false))
+            .chain(inner.map(|ix| (ix, true)))
+            .filter_map(|(ix, inner)| {
+                Some(InvokedInstruction {
+                    program: keys.get(ix.program_id_index as usize)?,
+                    data: &ix.data,
+                    inner,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Skips the magic byte, schema id and message indexes Confluent's wire format
+/// puts in front of an event, returning the encoded event.
+pub fn strip_confluent_framing(payload: &[u8]) -> Option<&[u8]> {
+    let (&0, rest) = payload.split_first()? else {
+        return None;
+    };
+    let mut rest = rest.get(4..)?;
+    let count = prost::encoding::decode_varint(&mut rest).ok()? >> 1;
+    for _ in 0..count {
+        prost::encoding::decode_varint(&mut rest).ok()?;
+    }
+    Some(rest)
+}
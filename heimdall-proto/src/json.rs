@@ -0,0 +1,98 @@
+//! Serializers the generated event types use for JSON output.
+
+use {
+    crate::{SlotStatus, account_meta, instruction_error, transaction_error},
+    base64::{Engine, prelude::BASE64_STANDARD},
+    serde::{Serializer, ser::SerializeSeq},
+};
+
+pub fn base58<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&bs58::encode(bytes).into_string())
+}
+
+pub fn base58_option<S: Serializer>(
+    bytes: &Option<Vec<u8>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match bytes {
+        Some(bytes) => base58(bytes, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+pub fn base58_list<S: Serializer>(list: &[Vec<u8>], serializer: S) -> Result<S::Ok, S::Error> {
+    let mut seq = serializer.serialize_seq(Some(list.len()))?;
+    for bytes in list {
+        seq.serialize_element(&bs58::encode(bytes).into_string())?;
+    }
+    seq.end()
+}
+
+pub fn base64<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&BASE64_STANDARD.encode(bytes))
+}
+
+pub fn slot_status<S: Serializer>(status: &i32, serializer: S) -> Result<S::Ok, S::Error> {
+    match SlotStatus::try_from(*status) {
+        Ok(status) => serializer.serialize_str(status.as_str_name()),
+        Err(_) => serializer.serialize_i32(*status),
+    }
+}
+
+/// Reward types are numbered like `solana_reward_info::RewardType`.
+pub fn reward_type<S: Serializer>(
+    reward_type: &Option<i32>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match reward_type {
+        None => serializer.serialize_none(),
+        Some(0) => serializer.serialize_str("Fee"),
+        Some(1) => serializer.serialize_str("Rent"),
+        Some(2) => serializer.serialize_str("Staking"),
+        Some(3) => serializer.serialize_str("Voting"),
+        Some(other) => serializer.serialize_i32(*other),
+    }
+}
+
+pub fn account_source<S: Serializer>(source: &i32, serializer: S) -> Result<S::Ok, S::Error> {
+    match account_meta::Source::try_from(*source) {
+        Ok(source) => serializer.serialize_str(source.as_str_name()),
+        Err(_) => serializer.serialize_i32(*source),
+    }
+}
+
+pub fn transaction_error_kind<S: Serializer>(kind: &i32, serializer: S) -> Result<S::Ok, S::Error> {
+    match transaction_error::Kind::try_from(*kind) {
+        Ok(kind) => serializer.serialize_str(kind.as_str_name()),
+        Err(_) => serializer.serialize_i32(*kind),
+    }
+}
+
+pub fn instruction_error_kind<S: Serializer>(kind: &i32, serializer: S) -> Result<S::Ok, S::Error> {
+    match instruction_error::Kind::try_from(*kind) {
+        Ok(kind) => serializer.serialize_str(kind.as_str_name()),
+        Err(_) => serializer.serialize_i32(*kind),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {crate::Reward, serde_json::json};
+
+    #[test]
+    fn missing_reward_type_is_null() {
+        let reward = |reward_type| {
+            serde_json::to_value(Reward {
+                reward_type,
+                ..Default::default()
+            })
+            .unwrap()["reward_type"]
+                .clone()
+        };
+
+        assert_eq!(reward(None), json!(null));
+        assert_eq!(reward(Some(0)), json!("Fee"));
+        assert_eq!(reward(Some(3)), json!("Voting"));
+        assert_eq!(reward(Some(9)), json!(9));
+    }
+}
@@ -0,0 +1,17 @@
+//! Types of the events Heimdall publishes, generated from `heimdall.proto`, with the
+//! helpers the plugin and its readers share.
+
+mod event;
+pub mod json;
+
+include!(concat!(
+    env!("OUT_DIR"),
+    "/heimdall.solana.geyser_plugin_kafka.types.rs"
+));
+
+pub use event::{
+    EventKind, InvokedInstruction, SCHEMA_VERSION, TransactionAccount, strip_confluent_framing,
+};
+
+/// The schema the events are encoded with.
+pub const PROTO_SCHEMA: &str = include_str!("../proto/heimdall.proto");